@@ -0,0 +1,79 @@
+-- Applied by statefi-indexer at startup; every statement must be idempotent.
+
+CREATE TABLE IF NOT EXISTS statefi_events (
+    seq             NUMERIC(20, 0) PRIMARY KEY,
+    name            TEXT NOT NULL,
+    slot            BIGINT NOT NULL,
+    signature       TEXT NOT NULL,
+    user_pubkey     TEXT,
+    mint            TEXT,
+    amount          NUMERIC(20, 0),
+    reference_hash  BYTEA,
+    emitted_at      TIMESTAMPTZ NOT NULL,
+    payload         BYTEA NOT NULL -- Raw Anchor event payload, for event-specific fields
+);
+CREATE INDEX IF NOT EXISTS statefi_events_user_idx ON statefi_events (user_pubkey, seq);
+CREATE INDEX IF NOT EXISTS statefi_events_reference_idx ON statefi_events (reference_hash);
+
+CREATE TABLE IF NOT EXISTS statefi_fiat_deposits (
+    address          TEXT PRIMARY KEY,
+    user_pubkey      TEXT NOT NULL,
+    mint             TEXT NOT NULL,
+    amount           NUMERIC(20, 0) NOT NULL,
+    reference_id     TEXT NOT NULL,
+    status           TEXT NOT NULL,
+    rejection_reason INTEGER,
+    created_at       TIMESTAMPTZ NOT NULL,
+    updated_at       TIMESTAMPTZ NOT NULL,
+    expires_at       TIMESTAMPTZ NOT NULL,
+    slot             BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS statefi_fiat_deposits_user_idx ON statefi_fiat_deposits (user_pubkey);
+
+CREATE TABLE IF NOT EXISTS statefi_fiat_withdrawals (
+    address          TEXT PRIMARY KEY,
+    user_pubkey      TEXT NOT NULL,
+    mint             TEXT NOT NULL,
+    amount           NUMERIC(20, 0) NOT NULL,
+    fee_amount       NUMERIC(20, 0) NOT NULL,
+    net_amount       NUMERIC(20, 0) NOT NULL,
+    reference_id     TEXT NOT NULL,
+    status           TEXT NOT NULL,
+    priority         TEXT NOT NULL,
+    rejection_reason INTEGER,
+    created_at       TIMESTAMPTZ NOT NULL,
+    updated_at       TIMESTAMPTZ NOT NULL,
+    expires_at       TIMESTAMPTZ NOT NULL,
+    slot             BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS statefi_fiat_withdrawals_user_idx ON statefi_fiat_withdrawals (user_pubkey);
+
+CREATE TABLE IF NOT EXISTS statefi_user_profiles (
+    address        TEXT PRIMARY KEY,
+    owner          TEXT NOT NULL,
+    kyc_tier       TEXT NOT NULL,
+    kyc_expires_at TIMESTAMPTZ NOT NULL,
+    is_frozen      BOOLEAN NOT NULL,
+    created_at     TIMESTAMPTZ NOT NULL,
+    slot           BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS statefi_vaults (
+    address    TEXT PRIMARY KEY,
+    owner      TEXT NOT NULL,
+    is_frozen  BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    slot       BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS statefi_tokens (
+    address         TEXT PRIMARY KEY,
+    mint            TEXT NOT NULL UNIQUE,
+    symbol          TEXT NOT NULL,
+    name            TEXT NOT NULL,
+    decimals        SMALLINT NOT NULL,
+    is_stable       BOOLEAN NOT NULL,
+    is_active       BOOLEAN NOT NULL,
+    total_deposited NUMERIC(20, 0) NOT NULL,
+    slot            BIGINT NOT NULL
+);
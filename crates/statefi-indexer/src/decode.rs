@@ -0,0 +1,274 @@
+//! Decoding of StateFi program events and account data into typed values.
+
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::{AccountDeserialize, AnchorDeserialize, Discriminator};
+use serde_json::Value;
+use statefi_protocol::{FiatDeposit, FiatWithdrawal, TokenWhitelist, UserProfile, Vault};
+use std::str::FromStr;
+
+/// Same hash the program stores in events in place of the plaintext reference id
+pub fn reference_hash(reference_id: &str) -> [u8; 32] {
+    hash(reference_id.as_bytes()).to_bytes()
+}
+
+/// Extracts the event payloads the program emitted with `emit_cpi!` from a `getTransaction`
+/// result (`json` encoding). Each event is a self-invocation whose data is the Anchor event
+/// instruction tag followed by the event's discriminator and Borsh data; unlike log-based events
+/// these survive log truncation. The program only accepts such invocations when signed by its own
+/// event authority PDA, so they cannot be forged by other callers.
+pub fn cpi_event_payloads(transaction: &Value, program_id: &Pubkey) -> Vec<Vec<u8>> {
+    let meta = &transaction["meta"];
+    // Static keys first, then addresses loaded from lookup tables, as the runtime orders them
+    let account_keys: Vec<Option<Pubkey>> = [
+        &transaction["transaction"]["message"]["accountKeys"],
+        &meta["loadedAddresses"]["writable"],
+        &meta["loadedAddresses"]["readonly"],
+    ]
+    .into_iter()
+    .filter_map(Value::as_array)
+    .flatten()
+    .map(|key| key.as_str().and_then(|key| Pubkey::from_str(key).ok()))
+    .collect();
+
+    let Some(inner_instructions) = meta["innerInstructions"].as_array() else {
+        return Vec::new();
+    };
+    inner_instructions
+        .iter()
+        .filter_map(|inner| inner["instructions"].as_array())
+        .flatten()
+        .filter(|instruction| {
+            instruction["programIdIndex"]
+                .as_u64()
+                .and_then(|index| account_keys.get(index as usize).copied().flatten())
+                == Some(*program_id)
+        })
+        .filter_map(|instruction| instruction["data"].as_str())
+        .filter_map(|data| bs58::decode(data).into_vec().ok())
+        .filter_map(|data| {
+            data.strip_prefix(EVENT_IX_TAG_LE.as_slice())
+                .map(<[u8]>::to_vec)
+        })
+        .collect()
+}
+
+/// Normalized view of an event: the fields every event shares, plus the user/mint/amount/
+/// reference columns carried by deposit and withdrawal lifecycle events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventRow {
+    pub seq: u64,
+    pub name: &'static str,
+    pub user: Option<Pubkey>,
+    pub mint: Option<Pubkey>,
+    pub amount: Option<u64>,
+    pub reference_hash: Option<[u8; 32]>,
+    pub timestamp: i64,
+}
+
+macro_rules! statefi_events {
+    (flow: [$($flow:ident),* $(,)?], other: [$($other:ident),* $(,)?] $(,)?) => {
+        /// Every event emitted by the program, decoded from its Anchor event payload
+        pub enum StateFiEvent {
+            $($flow(statefi_protocol::$flow),)*
+            $($other(statefi_protocol::$other),)*
+        }
+
+        impl StateFiEvent {
+            /// Decodes an event from its payload (discriminator followed by the Borsh-encoded
+            /// event). Returns `None` for payloads that are not StateFi events.
+            pub fn decode(payload: &[u8]) -> Option<Self> {
+                if payload.len() < 8 {
+                    return None;
+                }
+                let (discriminator, mut data) = payload.split_at(8);
+                $(
+                    if discriminator == statefi_protocol::$flow::DISCRIMINATOR {
+                        return statefi_protocol::$flow::deserialize(&mut data).ok().map(Self::$flow);
+                    }
+                )*
+                $(
+                    if discriminator == statefi_protocol::$other::DISCRIMINATOR {
+                        return statefi_protocol::$other::deserialize(&mut data).ok().map(Self::$other);
+                    }
+                )*
+                None
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$flow(_) => stringify!($flow),)*
+                    $(Self::$other(_) => stringify!($other),)*
+                }
+            }
+
+            pub fn row(&self) -> EventRow {
+                match self {
+                    $(Self::$flow(e) => EventRow {
+                        seq: e.seq,
+                        name: stringify!($flow),
+                        user: Some(e.user),
+                        mint: Some(e.mint),
+                        amount: Some(e.amount),
+                        reference_hash: Some(e.reference_hash),
+                        timestamp: e.timestamp,
+                    },)*
+                    $(Self::$other(e) => EventRow {
+                        seq: e.seq,
+                        name: stringify!($other),
+                        user: None,
+                        mint: None,
+                        amount: None,
+                        reference_hash: None,
+                        timestamp: e.timestamp,
+                    },)*
+                }
+            }
+        }
+    };
+}
+
+statefi_events! {
+    flow: [
+        DepositInitiated,
+        DepositCompleted,
+        DepositRejected,
+        DepositExpired,
+        WithdrawalInitiated,
+        WithdrawalCompleted,
+        WithdrawalCancelled,
+        WithdrawalExpired,
+        WithdrawalRejected,
+        WithdrawalVetoed,
+    ],
+    other: [
+        ProtocolFeeUpdated,
+        TreasuryWithdrawalQueued,
+        TreasuryWithdrawalExecuted,
+        TreasuryWithdrawalCancelled,
+        AdminActionProposed,
+        AdminActionApproved,
+        ChangeScheduled,
+        ScheduledChangeCancelled,
+        FeeScheduleUpdated,
+        AdminUpdated,
+        TimelockDelayUpdated,
+        TreasuryAuthorityUpdated,
+        RoleGranted,
+        RoleRevoked,
+        ProtocolPaused,
+        ProtocolUnpaused,
+        EmergencyWithdrawal,
+        WalletBlacklisted,
+        WalletUnblacklisted,
+        UserFreezeUpdated,
+        VaultFreezeUpdated,
+        UserProfileCreated,
+        UserProfileUpdated,
+        UserProfileClosed,
+        KycStatusUpdated,
+        VaultCreated,
+        VaultClosed,
+        TokenProposed,
+        TokenWhitelisted,
+        TokenUpdated,
+        WithdrawalProcessing,
+        FeesClaimed,
+        BalanceSnapshotRecorded,
+        VaultSwapped,
+        SavingsRateUpdated,
+        SavingsDeposited,
+        SavingsWithdrawn,
+        TermDepositOpened,
+        TermDepositRedeemed,
+        StakingRewardsFunded,
+        Staked,
+        Unstaked,
+        StakingRewardsClaimed,
+        CreditLineOpened,
+        CreditDrawn,
+        CreditRepaid,
+        CreditLineClosed,
+        StrategyDeposited,
+        StrategyWithdrawn,
+        FxRateQuoted,
+        RemittanceQuoteIssued,
+        CurrencyConverted,
+        InvoiceCreated,
+        InvoicePaid,
+        InvoiceCancelled,
+        MerchantPaid,
+        PaymentRefunded,
+        MerchantSettled,
+        SubscriptionCharged,
+        UsernameTransfer,
+        ClaimableTransferCreated,
+        ClaimableTransferClaimed,
+        ClaimableTransferRefunded,
+        SplitBillCreated,
+        SplitSharePaid,
+        SplitBillClosed,
+        EscrowOpened,
+        EscrowSettled,
+        BatchPayoutSent,
+        BatchPayoutCompleted,
+        GuardiansUpdated,
+        RecoveryProposed,
+        RecoveryApproved,
+        RecoveryCancelled,
+        AccountRecovered,
+        TransferAllowlistUpdated,
+        AllowlistEntryAdded,
+        AllowlistEntryRemoved,
+        CoSignerUpdated,
+        ClawbackInitiated,
+        ClawbackContested,
+        ClawbackResolved,
+        OutflowThrottled,
+        OutflowThrottleCleared,
+        PaymentStreamCreated,
+        PaymentStreamWithdrawn,
+        PaymentStreamCancelled,
+    ],
+}
+
+/// Program accounts the indexer mirrors into Postgres
+#[derive(Clone)]
+pub enum StateFiAccount {
+    FiatDeposit(FiatDeposit),
+    FiatWithdrawal(FiatWithdrawal),
+    UserProfile(UserProfile),
+    Vault(Vault),
+    TokenWhitelist(TokenWhitelist),
+}
+
+impl StateFiAccount {
+    /// Decodes raw account data by its Anchor discriminator. Other account types owned by the
+    /// program are ignored.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let discriminator = data.get(..8)?;
+        let mut data = data;
+        if discriminator == FiatDeposit::DISCRIMINATOR {
+            FiatDeposit::try_deserialize(&mut data)
+                .ok()
+                .map(Self::FiatDeposit)
+        } else if discriminator == FiatWithdrawal::DISCRIMINATOR {
+            FiatWithdrawal::try_deserialize(&mut data)
+                .ok()
+                .map(Self::FiatWithdrawal)
+        } else if discriminator == UserProfile::DISCRIMINATOR {
+            UserProfile::try_deserialize(&mut data)
+                .ok()
+                .map(Self::UserProfile)
+        } else if discriminator == Vault::DISCRIMINATOR {
+            Vault::try_deserialize(&mut data).ok().map(Self::Vault)
+        } else if discriminator == TokenWhitelist::DISCRIMINATOR {
+            TokenWhitelist::try_deserialize(&mut data)
+                .ok()
+                .map(Self::TokenWhitelist)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! Off-chain building blocks shared by the StateFi services: a websocket client for the
+//! program's log and account notifications, and decoders for its Anchor events and accounts.
+
+pub mod decode;
+pub mod rpc;
@@ -0,0 +1,110 @@
+//! statefi-indexer: follows the StateFi program over an RPC websocket and writes its events and
+//! accounts to Postgres as normalized rows.
+//!
+//! Configuration (environment):
+//! - `DATABASE_URL`: Postgres connection string (required)
+//! - `STATEFI_RPC_URL`: RPC HTTP endpoint used to fetch transactions, defaults to
+//!   `http://127.0.0.1:8899`
+//! - `STATEFI_RPC_WS_URL`: RPC websocket endpoint, defaults to `ws://127.0.0.1:8900`
+//! - `STATEFI_PROGRAM_ID`: program to follow, defaults to the id this crate was built against
+
+mod store;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{Context, Result};
+use statefi_indexer::decode::{cpi_event_payloads, StateFiAccount, StateFiEvent};
+use statefi_indexer::rpc::{Notification, ProgramSubscription, RpcClient};
+use std::str::FromStr;
+use std::time::Duration;
+use store::Store;
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_RPC_WS_URL: &str = "ws://127.0.0.1:8900";
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let database_url = std::env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
+    let rpc_url = std::env::var("STATEFI_RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+    let rpc_ws_url =
+        std::env::var("STATEFI_RPC_WS_URL").unwrap_or_else(|_| DEFAULT_RPC_WS_URL.to_string());
+    let program_id = match std::env::var("STATEFI_PROGRAM_ID") {
+        Ok(id) => Pubkey::from_str(&id).context("invalid STATEFI_PROGRAM_ID")?,
+        Err(_) => statefi_protocol::ID,
+    };
+
+    let store = Store::connect(&database_url).await?;
+    store.migrate().await?;
+    let mut indexer = Indexer {
+        store,
+        rpc: RpcClient::new(rpc_url),
+        program_id,
+        last_seq: None,
+    };
+    indexer.last_seq = indexer.store.last_seq().await?;
+
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match indexer.run(&rpc_ws_url).await {
+            Ok(()) => eprintln!("websocket closed by server, reconnecting"),
+            Err(err) => eprintln!("indexer error: {err:#}, reconnecting in {delay:?}"),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+struct Indexer {
+    store: Store,
+    rpc: RpcClient,
+    program_id: Pubkey,
+    last_seq: Option<u64>,
+}
+
+impl Indexer {
+    async fn run(&mut self, rpc_ws_url: &str) -> Result<()> {
+        let mut subscription = ProgramSubscription::connect(rpc_ws_url, &self.program_id).await?;
+        eprintln!("subscribed to {} via {rpc_ws_url}", self.program_id);
+
+        while let Some(notification) = subscription.next().await? {
+            match notification {
+                // Events from failed transactions were rolled back with the rest of the state
+                Notification::Logs { failed: true, .. } => {}
+                Notification::Logs {
+                    slot, signature, ..
+                } => {
+                    // Events are delivered as self-CPIs, so read them from the transaction's
+                    // inner instructions rather than from its (possibly truncated) logs
+                    let transaction = self.rpc.get_transaction(&signature).await?;
+                    for payload in cpi_event_payloads(&transaction, &self.program_id) {
+                        let Some(event) = StateFiEvent::decode(&payload) else {
+                            continue;
+                        };
+                        let row = event.row();
+                        self.check_sequence(row.seq);
+                        self.store
+                            .insert_event(slot, &signature, &row, &payload)
+                            .await?;
+                    }
+                }
+                Notification::Account { slot, pubkey, data } => {
+                    if let Some(account) = StateFiAccount::decode(&data) {
+                        self.store.upsert_account(slot, &pubkey, &account).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Event sequence numbers are assigned by the program's EventCounter, so any jump means
+    /// notifications were missed (e.g. while reconnecting) and need a backfill.
+    fn check_sequence(&mut self, seq: u64) {
+        if let Some(last) = self.last_seq {
+            if seq > last + 1 {
+                eprintln!("missed events {}..={}", last + 1, seq - 1);
+            }
+        }
+        self.last_seq = Some(self.last_seq.map_or(seq, |last| last.max(seq)));
+    }
+}
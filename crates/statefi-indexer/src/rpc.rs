@@ -0,0 +1,245 @@
+//! Minimal Solana RPC clients: a websocket subscription for the program's log and account
+//! notifications, and an HTTP client for fetching full transactions and program accounts.
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+const COMMITMENT: &str = "confirmed";
+const TRANSACTION_FETCH_ATTEMPTS: u32 = 5;
+const TRANSACTION_FETCH_DELAY: Duration = Duration::from_millis(500);
+
+/// A notification delivered by one of the program subscriptions
+#[derive(Clone, Debug)]
+pub enum Notification {
+    /// Logs of a transaction that mentions the program
+    Logs {
+        slot: u64,
+        signature: String,
+        failed: bool,
+        logs: Vec<String>,
+    },
+    /// New data for an account owned by the program
+    Account {
+        slot: u64,
+        pubkey: Pubkey,
+        data: Vec<u8>,
+    },
+}
+
+/// Websocket connection subscribed to `logsSubscribe` and `programSubscribe` for a program
+pub struct ProgramSubscription {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl ProgramSubscription {
+    pub async fn connect(url: &str, program_id: &Pubkey) -> Result<Self> {
+        let (mut socket, _) = connect_async(url)
+            .await
+            .with_context(|| format!("connecting to {url}"))?;
+
+        let program_id = program_id.to_string();
+        let requests = [
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "logsSubscribe",
+                "params": [{ "mentions": [program_id] }, { "commitment": COMMITMENT }],
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "programSubscribe",
+                "params": [program_id, { "encoding": "base64", "commitment": COMMITMENT }],
+            }),
+        ];
+        for request in requests {
+            socket.send(Message::Text(request.to_string())).await?;
+        }
+
+        Ok(Self { socket })
+    }
+
+    /// Waits for the next log or account notification. Returns `None` once the server closes
+    /// the connection.
+    pub async fn next(&mut self) -> Result<Option<Notification>> {
+        while let Some(message) = self.socket.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => return Ok(None),
+                _ => continue,
+            };
+            let value: Value = serde_json::from_str(&text)?;
+            if let Some(error) = value.get("error") {
+                bail!("subscription error: {error}");
+            }
+            if let Some(notification) = parse_notification(&value)? {
+                return Ok(Some(notification));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn parse_notification(value: &Value) -> Result<Option<Notification>> {
+    let method = value.get("method").and_then(Value::as_str);
+    let result = &value["params"]["result"];
+    let slot = result["context"]["slot"].as_u64().unwrap_or_default();
+    let inner = &result["value"];
+
+    match method {
+        Some("logsNotification") => {
+            let signature = inner["signature"]
+                .as_str()
+                .ok_or_else(|| anyhow!("logs notification without signature"))?
+                .to_string();
+            let logs = inner["logs"]
+                .as_array()
+                .map(|logs| {
+                    logs.iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(Some(Notification::Logs {
+                slot,
+                signature,
+                failed: !inner["err"].is_null(),
+                logs,
+            }))
+        }
+        Some("programNotification") => {
+            let pubkey = inner["pubkey"]
+                .as_str()
+                .ok_or_else(|| anyhow!("program notification without pubkey"))?;
+            let data = inner["account"]["data"][0]
+                .as_str()
+                .ok_or_else(|| anyhow!("program notification without base64 data"))?;
+            Ok(Some(Notification::Account {
+                slot,
+                pubkey: Pubkey::from_str(pubkey)?,
+                data: STANDARD.decode(data)?,
+            }))
+        }
+        // Subscription confirmations and anything else we did not ask for
+        _ => Ok(None),
+    }
+}
+
+/// JSON-RPC client for the HTTP endpoint
+pub struct RpcClient {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.into(),
+        }
+    }
+
+    /// Fetches a confirmed transaction with its inner instructions (`json` encoding). A log
+    /// notification can arrive before the node serves the transaction, so a missing result is
+    /// retried briefly.
+    pub async fn get_transaction(&self, signature: &str) -> Result<Value> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getTransaction",
+            "params": [
+                signature,
+                {
+                    "encoding": "json",
+                    "commitment": COMMITMENT,
+                    "maxSupportedTransactionVersion": 0,
+                },
+            ],
+        });
+
+        for _ in 0..TRANSACTION_FETCH_ATTEMPTS {
+            let mut response: Value = self
+                .http
+                .post(&self.url)
+                .json(&request)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            if let Some(error) = response.get("error") {
+                bail!("getTransaction {signature} failed: {error}");
+            }
+            if !response["result"].is_null() {
+                return Ok(response["result"].take());
+            }
+            tokio::time::sleep(TRANSACTION_FETCH_DELAY).await;
+        }
+        bail!("transaction {signature} not available")
+    }
+
+    /// Fetches every account of the program whose data starts with `discriminator`, with the
+    /// slot the snapshot was taken at
+    pub async fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        discriminator: &[u8],
+    ) -> Result<(u64, Vec<(Pubkey, Vec<u8>)>)> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getProgramAccounts",
+            "params": [
+                program_id.to_string(),
+                {
+                    "encoding": "base64",
+                    "commitment": COMMITMENT,
+                    "withContext": true,
+                    "filters": [
+                        { "memcmp": { "offset": 0, "bytes": bs58::encode(discriminator).into_string() } },
+                    ],
+                },
+            ],
+        });
+
+        let response: Value = self
+            .http
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("getProgramAccounts {program_id} failed: {error}");
+        }
+        let result = &response["result"];
+        let slot = result["context"]["slot"].as_u64().unwrap_or_default();
+        let accounts = result["value"]
+            .as_array()
+            .ok_or_else(|| anyhow!("getProgramAccounts {program_id} without accounts"))?
+            .iter()
+            .map(|account| {
+                let pubkey = account["pubkey"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("program account without pubkey"))?;
+                let data = account["account"]["data"][0]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("program account without base64 data"))?;
+                Ok((Pubkey::from_str(pubkey)?, STANDARD.decode(data)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok((slot, accounts))
+    }
+}
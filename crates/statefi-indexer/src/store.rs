@@ -0,0 +1,223 @@
+//! Postgres persistence for decoded events and accounts.
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Result;
+use statefi_indexer::decode::{EventRow, StateFiAccount};
+use tokio_postgres::{Client, NoTls};
+
+const SCHEMA: &str = include_str!("../schema.sql");
+
+pub struct Store {
+    client: Client,
+}
+
+impl Store {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let (client, connection) = tokio_postgres::connect(database_url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                eprintln!("postgres connection error: {err}");
+            }
+        });
+        Ok(Self { client })
+    }
+
+    pub async fn migrate(&self) -> Result<()> {
+        self.client.batch_execute(SCHEMA).await?;
+        Ok(())
+    }
+
+    /// Highest event sequence number already stored, used to detect gaps after a restart
+    pub async fn last_seq(&self) -> Result<Option<u64>> {
+        let row = self
+            .client
+            .query_one("SELECT MAX(seq)::TEXT FROM statefi_events", &[])
+            .await?;
+        let seq: Option<String> = row.get(0);
+        Ok(seq.map(|seq| seq.parse()).transpose()?)
+    }
+
+    /// Inserts an event row; replays of an already indexed sequence number are ignored
+    pub async fn insert_event(
+        &self,
+        slot: u64,
+        signature: &str,
+        event: &EventRow,
+        payload: &[u8],
+    ) -> Result<()> {
+        self.client
+            .execute(
+                "INSERT INTO statefi_events
+                    (seq, name, slot, signature, user_pubkey, mint, amount, reference_hash, emitted_at, payload)
+                 VALUES ($1::TEXT::NUMERIC, $2, $3, $4, $5, $6, $7::TEXT::NUMERIC, $8, to_timestamp($9::BIGINT), $10)
+                 ON CONFLICT (seq) DO NOTHING",
+                &[
+                    &event.seq.to_string(),
+                    &event.name,
+                    &(slot as i64),
+                    &signature,
+                    &event.user.map(|user| user.to_string()),
+                    &event.mint.map(|mint| mint.to_string()),
+                    &event.amount.map(|amount| amount.to_string()),
+                    &event.reference_hash.as_ref().map(|hash| hash.as_slice()),
+                    &event.timestamp,
+                    &payload,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Upserts the latest state of an account. Notifications can arrive out of order across
+    /// reconnects, so a row is only overwritten by data from the same or a later slot.
+    pub async fn upsert_account(
+        &self,
+        slot: u64,
+        address: &Pubkey,
+        account: &StateFiAccount,
+    ) -> Result<()> {
+        let slot = slot as i64;
+        let address = address.to_string();
+        match account {
+            StateFiAccount::FiatDeposit(deposit) => {
+                self.client
+                    .execute(
+                        "INSERT INTO statefi_fiat_deposits
+                            (address, user_pubkey, mint, amount, reference_id, status, rejection_reason,
+                             created_at, updated_at, expires_at, slot)
+                         VALUES ($1, $2, $3, $4::TEXT::NUMERIC, $5, $6, $7,
+                                 to_timestamp($8::BIGINT), to_timestamp($9::BIGINT), to_timestamp($10::BIGINT), $11)
+                         ON CONFLICT (address) DO UPDATE SET
+                            status = EXCLUDED.status,
+                            rejection_reason = EXCLUDED.rejection_reason,
+                            updated_at = EXCLUDED.updated_at,
+                            slot = EXCLUDED.slot
+                         WHERE statefi_fiat_deposits.slot <= EXCLUDED.slot",
+                        &[
+                            &address,
+                            &deposit.user.to_string(),
+                            &deposit.mint.to_string(),
+                            &deposit.amount.to_string(),
+                            &deposit.reference_id,
+                            &format!("{:?}", deposit.status),
+                            &deposit.rejection_reason.map(i32::from),
+                            &deposit.created_at,
+                            &deposit.updated_at,
+                            &deposit.expiry_ts,
+                            &slot,
+                        ],
+                    )
+                    .await?;
+            }
+            StateFiAccount::FiatWithdrawal(withdrawal) => {
+                self.client
+                    .execute(
+                        "INSERT INTO statefi_fiat_withdrawals
+                            (address, user_pubkey, mint, amount, fee_amount, net_amount, reference_id, status,
+                             priority, rejection_reason, created_at, updated_at, expires_at, slot)
+                         VALUES ($1, $2, $3, $4::TEXT::NUMERIC, $5::TEXT::NUMERIC, $6::TEXT::NUMERIC, $7, $8,
+                                 $9, $10, to_timestamp($11::BIGINT), to_timestamp($12::BIGINT),
+                                 to_timestamp($13::BIGINT), $14)
+                         ON CONFLICT (address) DO UPDATE SET
+                            fee_amount = EXCLUDED.fee_amount,
+                            net_amount = EXCLUDED.net_amount,
+                            status = EXCLUDED.status,
+                            priority = EXCLUDED.priority,
+                            rejection_reason = EXCLUDED.rejection_reason,
+                            updated_at = EXCLUDED.updated_at,
+                            expires_at = EXCLUDED.expires_at,
+                            slot = EXCLUDED.slot
+                         WHERE statefi_fiat_withdrawals.slot <= EXCLUDED.slot",
+                        &[
+                            &address,
+                            &withdrawal.user.to_string(),
+                            &withdrawal.mint.to_string(),
+                            &withdrawal.amount.to_string(),
+                            &withdrawal.fee_amount.to_string(),
+                            &withdrawal.net_amount.to_string(),
+                            &withdrawal.reference_id,
+                            &format!("{:?}", withdrawal.status),
+                            &format!("{:?}", withdrawal.priority),
+                            &withdrawal.rejection_reason.map(i32::from),
+                            &withdrawal.created_at,
+                            &withdrawal.updated_at,
+                            &withdrawal.expires_at,
+                            &slot,
+                        ],
+                    )
+                    .await?;
+            }
+            StateFiAccount::UserProfile(profile) => {
+                self.client
+                    .execute(
+                        "INSERT INTO statefi_user_profiles
+                            (address, owner, kyc_tier, kyc_expires_at, is_frozen, created_at, slot)
+                         VALUES ($1, $2, $3, to_timestamp($4::BIGINT), $5, to_timestamp($6::BIGINT), $7)
+                         ON CONFLICT (address) DO UPDATE SET
+                            kyc_tier = EXCLUDED.kyc_tier,
+                            kyc_expires_at = EXCLUDED.kyc_expires_at,
+                            is_frozen = EXCLUDED.is_frozen,
+                            slot = EXCLUDED.slot
+                         WHERE statefi_user_profiles.slot <= EXCLUDED.slot",
+                        &[
+                            &address,
+                            &profile.owner.to_string(),
+                            &format!("{:?}", profile.kyc_tier),
+                            &profile.kyc_expires_at,
+                            &profile.is_frozen,
+                            &profile.created_at,
+                            &slot,
+                        ],
+                    )
+                    .await?;
+            }
+            StateFiAccount::Vault(vault) => {
+                self.client
+                    .execute(
+                        "INSERT INTO statefi_vaults (address, owner, is_frozen, created_at, slot)
+                         VALUES ($1, $2, $3, to_timestamp($4::BIGINT), $5)
+                         ON CONFLICT (address) DO UPDATE SET
+                            is_frozen = EXCLUDED.is_frozen,
+                            slot = EXCLUDED.slot
+                         WHERE statefi_vaults.slot <= EXCLUDED.slot",
+                        &[
+                            &address,
+                            &vault.owner.to_string(),
+                            &vault.is_frozen,
+                            &vault.created_at,
+                            &slot,
+                        ],
+                    )
+                    .await?;
+            }
+            StateFiAccount::TokenWhitelist(token) => {
+                self.client
+                    .execute(
+                        "INSERT INTO statefi_tokens
+                            (address, mint, symbol, name, decimals, is_stable, is_active, total_deposited, slot)
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8::TEXT::NUMERIC, $9)
+                         ON CONFLICT (address) DO UPDATE SET
+                            symbol = EXCLUDED.symbol,
+                            name = EXCLUDED.name,
+                            is_active = EXCLUDED.is_active,
+                            total_deposited = EXCLUDED.total_deposited,
+                            slot = EXCLUDED.slot
+                         WHERE statefi_tokens.slot <= EXCLUDED.slot",
+                        &[
+                            &address,
+                            &token.mint.to_string(),
+                            &token.symbol,
+                            &token.name,
+                            &i16::from(token.decimals),
+                            &token.is_stable,
+                            &token.is_active,
+                            &token.total_deposited.to_string(),
+                            &slot,
+                        ],
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! statefi-relayer: watches FiatDeposit and FiatWithdrawal accounts over an RPC websocket and
+//! pushes a signed webhook to every configured endpoint whenever their status changes. On every
+//! (re)connect it diffs a snapshot of those accounts against the saved state, so changes made
+//! while it was away are notified too.
+//!
+//! Configuration (environment):
+//! - `STATEFI_WEBHOOK_URLS`: comma-separated endpoints to POST to (required)
+//! - `STATEFI_WEBHOOK_SECRET`: shared secret for the `X-StateFi-Signature` HMAC (required)
+//! - `STATEFI_WEBHOOK_MAX_ATTEMPTS`: delivery attempts per endpoint, defaults to 8
+//! - `STATEFI_RPC_URL`: RPC HTTP endpoint used for snapshots, defaults to `http://127.0.0.1:8899`
+//! - `STATEFI_RPC_WS_URL`: RPC websocket endpoint, defaults to `ws://127.0.0.1:8900`
+//! - `STATEFI_RELAYER_STATE`: file the notified statuses are saved to, defaults to
+//!   `statefi-relayer-state.json`
+//! - `STATEFI_PROGRAM_ID`: program to follow, defaults to the id this crate was built against
+
+mod state;
+mod webhook;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Discriminator;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use state::RelayerState;
+use statefi_indexer::decode::{reference_hash, StateFiAccount};
+use statefi_indexer::rpc::{Notification, ProgramSubscription, RpcClient};
+use statefi_protocol::{DepositStatus, FiatDeposit, FiatWithdrawal, WithdrawalStatus};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use webhook::Webhooks;
+
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_RPC_WS_URL: &str = "ws://127.0.0.1:8900";
+const DEFAULT_STATE_PATH: &str = "statefi-relayer-state.json";
+const DEFAULT_MAX_ATTEMPTS: u32 = 8;
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let endpoints: Vec<String> = std::env::var("STATEFI_WEBHOOK_URLS")
+        .context("STATEFI_WEBHOOK_URLS must be set")?
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect();
+    anyhow::ensure!(
+        !endpoints.is_empty(),
+        "STATEFI_WEBHOOK_URLS has no endpoints"
+    );
+    let secret =
+        std::env::var("STATEFI_WEBHOOK_SECRET").context("STATEFI_WEBHOOK_SECRET must be set")?;
+    let max_attempts = match std::env::var("STATEFI_WEBHOOK_MAX_ATTEMPTS") {
+        Ok(attempts) => attempts
+            .parse()
+            .context("invalid STATEFI_WEBHOOK_MAX_ATTEMPTS")?,
+        Err(_) => DEFAULT_MAX_ATTEMPTS,
+    };
+    let rpc_url = std::env::var("STATEFI_RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+    let rpc_ws_url =
+        std::env::var("STATEFI_RPC_WS_URL").unwrap_or_else(|_| DEFAULT_RPC_WS_URL.to_string());
+    let state_path = PathBuf::from(
+        std::env::var("STATEFI_RELAYER_STATE").unwrap_or_else(|_| DEFAULT_STATE_PATH.to_string()),
+    );
+    let program_id = match std::env::var("STATEFI_PROGRAM_ID") {
+        Ok(id) => Pubkey::from_str(&id).context("invalid STATEFI_PROGRAM_ID")?,
+        Err(_) => statefi_protocol::ID,
+    };
+
+    let saved_state = RelayerState::load(&state_path)?;
+    let mut relayer = Relayer {
+        webhooks: Arc::new(Webhooks::new(endpoints, secret.into_bytes(), max_attempts)?),
+        rpc: RpcClient::new(rpc_url),
+        program_id,
+        needs_baseline: saved_state.is_none(),
+        state: saved_state.unwrap_or_default(),
+        state_path,
+    };
+
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match relayer.run(&rpc_ws_url).await {
+            Ok(()) => eprintln!("websocket closed by server, reconnecting"),
+            Err(err) => eprintln!("relayer error: {err:#}, reconnecting in {delay:?}"),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+struct Relayer {
+    webhooks: Arc<Webhooks>,
+    rpc: RpcClient,
+    program_id: Pubkey,
+    /// Without saved state the first snapshot is taken as the starting point rather than replayed
+    needs_baseline: bool,
+    state: RelayerState,
+    state_path: PathBuf,
+}
+
+impl Relayer {
+    async fn run(&mut self, rpc_ws_url: &str) -> Result<()> {
+        let mut subscription = ProgramSubscription::connect(rpc_ws_url, &self.program_id).await?;
+        eprintln!("subscribed to {} via {rpc_ws_url}", self.program_id);
+        // Subscribed first, so changes made while the snapshot is taken still arrive as
+        // notifications; the saved state filters out the overlap
+        self.sync_snapshot().await?;
+
+        while let Some(notification) = subscription.next().await? {
+            let Notification::Account { slot, pubkey, data } = notification else {
+                continue;
+            };
+            let Some(update) = StateFiAccount::decode(&data)
+                .and_then(|account| status_update(slot, &pubkey, &account))
+            else {
+                continue;
+            };
+            let caught_up_at = self.state.caught_up_at.max(update.updated_at);
+            self.notify(pubkey, update);
+            self.state.caught_up_at = caught_up_at;
+            self.state.save(&self.state_path)?;
+        }
+        Ok(())
+    }
+
+    /// Notifies every deposit and withdrawal whose status changed since the saved state
+    async fn sync_snapshot(&mut self) -> Result<()> {
+        let mut live = HashSet::new();
+        let mut caught_up_at = self.state.caught_up_at;
+        for discriminator in [FiatDeposit::DISCRIMINATOR, FiatWithdrawal::DISCRIMINATOR] {
+            let (slot, accounts) = self
+                .rpc
+                .get_program_accounts(&self.program_id, &discriminator)
+                .await?;
+            for (pubkey, data) in accounts {
+                let Some(update) = StateFiAccount::decode(&data)
+                    .and_then(|account| status_update(slot, &pubkey, &account))
+                else {
+                    continue;
+                };
+                live.insert(pubkey);
+                // Compared against the state as saved, not as advanced by this snapshot
+                caught_up_at = caught_up_at.max(update.updated_at);
+                if self.needs_baseline {
+                    self.state.record(pubkey, update.status, update.is_terminal);
+                } else {
+                    self.notify(pubkey, update);
+                }
+            }
+        }
+        self.state.retain(|pubkey| live.contains(pubkey));
+        self.state.caught_up_at = caught_up_at;
+        self.state.save(&self.state_path)?;
+        self.needs_baseline = false;
+        eprintln!(
+            "snapshot synced, {} deposits and withdrawals checked",
+            live.len()
+        );
+        Ok(())
+    }
+
+    fn notify(&mut self, pubkey: Pubkey, update: StatusUpdate) {
+        if !self.state.is_new(
+            &pubkey,
+            &update.status,
+            update.is_terminal,
+            update.updated_at,
+        ) {
+            return;
+        }
+
+        // Stable per (account, status) so receivers can deduplicate redeliveries
+        let delivery_id = format!("{pubkey}:{}", update.status);
+        self.webhooks
+            .dispatch(delivery_id, update.payload.to_string());
+        self.state.record(pubkey, update.status, update.is_terminal);
+    }
+}
+
+/// A deposit or withdrawal status to notify
+struct StatusUpdate {
+    status: String,
+    /// Later changes are still possible (a completed deposit can be reversed), but the saved
+    /// state no longer needs to remember the account
+    is_terminal: bool,
+    updated_at: i64,
+    payload: Value,
+}
+
+/// Builds the webhook body for a fiat deposit or withdrawal, along with its status
+fn status_update(slot: u64, address: &Pubkey, account: &StateFiAccount) -> Option<StatusUpdate> {
+    match account {
+        StateFiAccount::FiatDeposit(deposit) => {
+            let status = format!("{:?}", deposit.status);
+            let payload = json!({
+                "type": "fiat_deposit",
+                "address": address.to_string(),
+                "user": deposit.user.to_string(),
+                "mint": deposit.mint.to_string(),
+                "status": status,
+                "amount": deposit.amount.to_string(),
+                "reference_hash": hex::encode(reference_hash(&deposit.reference_id)),
+                "rejection_reason": deposit.rejection_reason,
+                "updated_at": deposit.updated_at,
+                "slot": slot,
+            });
+            Some(StatusUpdate {
+                status,
+                is_terminal: deposit.status != DepositStatus::Pending,
+                updated_at: deposit.updated_at,
+                payload,
+            })
+        }
+        StateFiAccount::FiatWithdrawal(withdrawal) => {
+            let status = format!("{:?}", withdrawal.status);
+            let payload = json!({
+                "type": "fiat_withdrawal",
+                "address": address.to_string(),
+                "user": withdrawal.user.to_string(),
+                "mint": withdrawal.mint.to_string(),
+                "status": status,
+                "amount": withdrawal.amount.to_string(),
+                "fee_amount": withdrawal.fee_amount.to_string(),
+                "net_amount": withdrawal.net_amount.to_string(),
+                "payout_currency": withdrawal
+                    .remittance_quote
+                    .map(|_| String::from_utf8_lossy(&withdrawal.payout_currency).into_owned()),
+                "payout_amount": withdrawal.payout_amount.to_string(),
+                "reference_hash": hex::encode(reference_hash(&withdrawal.reference_id)),
+                "rejection_reason": withdrawal.rejection_reason,
+                "updated_at": withdrawal.updated_at,
+                "slot": slot,
+            });
+            Some(StatusUpdate {
+                status,
+                is_terminal: !matches!(
+                    withdrawal.status,
+                    WithdrawalStatus::Pending
+                        | WithdrawalStatus::Processing
+                        | WithdrawalStatus::Delayed
+                ),
+                updated_at: withdrawal.updated_at,
+                payload,
+            })
+        }
+        _ => None,
+    }
+}
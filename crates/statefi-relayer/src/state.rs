@@ -0,0 +1,93 @@
+//! What the relayer has notified so far, persisted so a restart neither replays nor misses
+//! status changes.
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Default)]
+pub struct RelayerState {
+    /// Latest `updated_at` among the account states handled. Anything that changed while the
+    /// relayer was away carries a timestamp at least this recent
+    pub caught_up_at: i64,
+    /// Last status notified per account. Accounts are dropped once they reach a terminal status
+    /// so the map only holds deposits and withdrawals still in flight
+    last_status: HashMap<Pubkey, String>,
+}
+
+impl RelayerState {
+    /// Reads the saved state, or `None` if the relayer has never run with this path
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+        };
+        let value: Value =
+            serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        let caught_up_at = value["caught_up_at"].as_i64().unwrap_or_default();
+        let last_status = value["last_status"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(pubkey, status)| {
+                Some((Pubkey::from_str(pubkey).ok()?, status.as_str()?.to_string()))
+            })
+            .collect();
+        Ok(Some(Self {
+            caught_up_at,
+            last_status,
+        }))
+    }
+
+    /// Writes the state through a temporary file so a crash never leaves it half written
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let last_status: Map<String, Value> = self
+            .last_status
+            .iter()
+            .map(|(pubkey, status)| (pubkey.to_string(), Value::from(status.as_str())))
+            .collect();
+        let value = json!({
+            "caught_up_at": self.caught_up_at,
+            "last_status": last_status,
+        });
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, value.to_string())
+            .with_context(|| format!("writing {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path).with_context(|| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Whether `status` still needs notifying for the account. An account missing from the map
+    /// at a terminal status was either notified before being dropped or finished before the
+    /// relayer started, unless it changed after `caught_up_at`
+    pub fn is_new(
+        &self,
+        pubkey: &Pubkey,
+        status: &str,
+        is_terminal: bool,
+        updated_at: i64,
+    ) -> bool {
+        match self.last_status.get(pubkey) {
+            Some(last_status) => last_status != status,
+            None => !is_terminal || updated_at >= self.caught_up_at,
+        }
+    }
+
+    /// Records `status` as notified, dropping the account once it is terminal
+    pub fn record(&mut self, pubkey: Pubkey, status: String, is_terminal: bool) {
+        if is_terminal {
+            self.last_status.remove(&pubkey);
+        } else {
+            self.last_status.insert(pubkey, status);
+        }
+    }
+
+    /// Forgets accounts that no longer exist on chain
+    pub fn retain(&mut self, live: impl Fn(&Pubkey) -> bool) {
+        self.last_status.retain(|pubkey, _| live(pubkey));
+    }
+}
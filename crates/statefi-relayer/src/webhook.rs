@@ -0,0 +1,122 @@
+//! Signed webhook delivery with retry and exponential backoff.
+
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const SIGNATURE_HEADER: &str = "X-StateFi-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-StateFi-Timestamp";
+pub const DELIVERY_HEADER: &str = "X-StateFi-Delivery";
+
+/// HMAC-SHA256 over `"{timestamp}.{body}"`, hex encoded. Receivers recompute it with the shared
+/// secret and should reject stale timestamps to prevent replays.
+pub fn sign(secret: &[u8], timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub struct Webhooks {
+    client: reqwest::Client,
+    endpoints: Vec<String>,
+    secret: Vec<u8>,
+    max_attempts: u32,
+}
+
+impl Webhooks {
+    pub fn new(
+        endpoints: Vec<String>,
+        secret: Vec<u8>,
+        max_attempts: u32,
+    ) -> reqwest::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            endpoints,
+            secret,
+            max_attempts,
+        })
+    }
+
+    /// Queues delivery of `body` to every endpoint. Each endpoint retries independently in the
+    /// background so a slow receiver never holds up the subscription.
+    pub fn dispatch(self: &Arc<Self>, delivery_id: String, body: String) {
+        for endpoint in self.endpoints.clone() {
+            let webhooks = Arc::clone(self);
+            let delivery_id = delivery_id.clone();
+            let body = body.clone();
+            tokio::spawn(async move {
+                webhooks.deliver(&endpoint, &delivery_id, &body).await;
+            });
+        }
+    }
+
+    async fn deliver(&self, endpoint: &str, delivery_id: &str, body: &str) {
+        let mut delay = INITIAL_RETRY_DELAY;
+        for attempt in 1..=self.max_attempts {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let result = self
+                .client
+                .post(endpoint)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(
+                    SIGNATURE_HEADER,
+                    format!("sha256={}", sign(&self.secret, timestamp, body)),
+                )
+                .header(DELIVERY_HEADER, delivery_id)
+                .body(body.to_string())
+                .send()
+                .await;
+
+            match result {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) if !is_retryable(response.status()) => {
+                    eprintln!(
+                        "webhook {delivery_id} rejected by {endpoint} with {}, not retrying",
+                        response.status()
+                    );
+                    return;
+                }
+                Ok(response) => eprintln!(
+                    "webhook {delivery_id} to {endpoint} failed with {} (attempt {attempt}/{})",
+                    response.status(),
+                    self.max_attempts
+                ),
+                Err(err) => eprintln!(
+                    "webhook {delivery_id} to {endpoint} failed: {err} (attempt {attempt}/{})",
+                    self.max_attempts
+                ),
+            }
+
+            if attempt < self.max_attempts {
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+        eprintln!(
+            "webhook {delivery_id} to {endpoint} dropped after {} attempts",
+            self.max_attempts
+        );
+    }
+}
+
+/// Client errors other than timeouts and rate limiting will not succeed on a retry
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
@@ -0,0 +1,68 @@
+//! Stand-in for the DEX adapter `swap_in_vault` calls through, so vault swaps can be exercised
+//! on localnet. Swaps 1:1 against a pool of output tokens owned by the adapter
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("2kfCxJAco7GodkhzeAHLDeY4viQdaSSF3vU2s8aDV8Ha");
+
+#[program]
+pub mod mock_swap_adapter {
+    use super::*;
+
+    /// Take `amount_in` from the vault into the pool and pay the same amount of the output mint
+    /// back. The caller enforces `min_amount_out`, so the adapter ignores it
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, _min_amount_out: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.vault_input_token_account.to_account_info(),
+            mint: ctx.accounts.input_mint.to_account_info(),
+            to: ctx.accounts.pool_input_token_account.to_account_info(),
+            authority: ctx.accounts.vault.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount_in, ctx.accounts.input_mint.decimals)?;
+
+        let seeds = &[b"pool".as_ref(), &[ctx.bumps.pool_authority]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.pool_output_token_account.to_account_info(),
+            mint: ctx.accounts.output_mint.to_account_info(),
+            to: ctx.accounts.vault_output_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        token_interface::transfer_checked(cpi_ctx, amount_in, ctx.accounts.output_mint.decimals)
+    }
+}
+
+/// The accounts `swap_in_vault` passes every adapter, followed by the pool's
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    pub vault: Signer<'info>,
+
+    #[account(mut)]
+    pub vault_input_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub vault_output_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub input_mint: InterfaceAccount<'info, Mint>,
+
+    pub output_mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    #[account(mut)]
+    pub pool_input_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub pool_output_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA owning the pool's token accounts
+    #[account(seeds = [b"pool"], bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+}
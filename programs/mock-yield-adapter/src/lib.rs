@@ -0,0 +1,63 @@
+//! Stand-in for the lending adapters the treasury and vault yield strategies deploy through, so
+//! deployments can be exercised on localnet. Deposits sit in a pool token account owned by the
+//! adapter; tests mint extra into the pool to stand for accrued yield
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("ATits75xxDL6v9Er9Nb99cnDVD3yaGcEGJ8kt1MFmg7a");
+
+#[program]
+pub mod mock_yield_adapter {
+    use super::*;
+
+    /// Move `amount` from the depositor's token account into the pool
+    pub fn deposit(ctx: Context<PoolTransfer>, amount: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.pool_token_account.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)
+    }
+
+    /// Pay `amount` out of the pool back to the depositor's token account
+    pub fn withdraw(ctx: Context<PoolTransfer>, amount: u64) -> Result<()> {
+        let seeds = &[b"pool".as_ref(), &[ctx.bumps.pool_authority]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.pool_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)
+    }
+}
+
+/// The accounts the treasury and strategy instructions pass every adapter, followed by the pool's
+#[derive(Accounts)]
+pub struct PoolTransfer<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    #[account(mut)]
+    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: PDA owning the pool's token account
+    #[account(seeds = [b"pool"], bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+}
@@ -2,9 +2,18 @@ import * as anchor from "@project-serum/anchor";
 import { Program } from "@project-serum/anchor";
 import { StatefiProtocol } from "../target/types/statefi_protocol";
 import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID, createMint, createAccount } from "@solana/spl-token";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  mintTo,
+} from "@solana/spl-token";
 import { expect, assert } from "chai";
 import { AnchorError } from "@project-serum/anchor";
+import { createHash } from "crypto";
 
 describe("statefi-protocol", () => {
   // Configure the client to use the local cluster
@@ -23,16 +32,29 @@ describe("statefi-protocol", () => {
 // Test accounts
 const admin = Keypair.generate();
 const user = Keypair.generate();
+const treasuryAuthority = Keypair.generate();
+const kycVerifier = Keypair.generate();
 let protocolConfig: PublicKey;
+let adminMultisig: PublicKey;
+let feeSchedule: PublicKey;
+let eventCounter: PublicKey;
+let eventAuthority: PublicKey;
+let protocolStats: PublicKey;
 let userProfile: PublicKey;
+let userTxIndex: PublicKey;
+let userBlacklist: PublicKey;
+let userLimits: PublicKey;
 let vault: PublicKey;
 let tokenWhitelist: PublicKey;
 let mint: PublicKey;
 let userTokenAccount: PublicKey;
 let treasuryTokenAccount: PublicKey;
+let treasury: PublicKey;
 
 // Test constants
-const ADMIN_FEE_BASIS_POINTS = 100; // 1%
+const DEPOSIT_FEE_BPS = 100; // 1%
+const WITHDRAWAL_FEE_BPS = 50; // 0.5%
+const TIMELOCK_DELAY = 0; // Scheduled changes are executable immediately in tests
 
 before(async () => {
   // Airdrop SOL to admin and user
@@ -55,6 +77,16 @@ before(async () => {
   console.log(`Admin balance: ${adminBalance / anchor.web3.LAMPORTS_PER_SOL} SOL`);
   console.log(`User balance: ${userBalance / anchor.web3.LAMPORTS_PER_SOL} SOL`);
 
+  [userBlacklist] = PublicKey.findProgramAddressSync(
+    [Buffer.from("blacklist"), user.publicKey.toBuffer()],
+    program.programId
+  );
+
+  [userLimits] = PublicKey.findProgramAddressSync(
+    [Buffer.from("user_limits"), user.publicKey.toBuffer()],
+    program.programId
+  );
+
   // Create test token mint with confirmation
   mint = await createMint(
     provider.connection,
@@ -78,7 +110,12 @@ it("Initialize protocol", async () => {
   );
 
   await program.methods
-    .initializeProtocol(ADMIN_FEE_BASIS_POINTS)
+    .initializeProtocol(
+      DEPOSIT_FEE_BPS,
+      WITHDRAWAL_FEE_BPS,
+      new anchor.BN(TIMELOCK_DELAY),
+      treasuryAuthority.publicKey
+    )
     .accounts({
       admin: admin.publicKey,
       protocolConfig,
@@ -89,7 +126,423 @@ it("Initialize protocol", async () => {
 
   const config = await program.account.protocolConfig.fetch(protocolConfig);
   expect(config.admin.toString()).to.equal(admin.publicKey.toString());
-  expect(config.adminFeeBasisPoints).to.equal(ADMIN_FEE_BASIS_POINTS);
+  expect(config.depositFeeBps).to.equal(DEPOSIT_FEE_BPS);
+  expect(config.withdrawalFeeBps).to.equal(WITHDRAWAL_FEE_BPS);
+  expect(config.timelockDelay.toNumber()).to.equal(TIMELOCK_DELAY);
+  expect(config.treasuryAuthority.toString()).to.equal(treasuryAuthority.publicKey.toString());
+});
+
+it("Initialize admin multisig", async () => {
+  [adminMultisig] = PublicKey.findProgramAddressSync(
+    [Buffer.from("admin_multisig")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeAdminMultisig([admin.publicKey], 1)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      adminMultisig,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const multisig = await program.account.adminMultisig.fetch(adminMultisig);
+  expect(multisig.threshold).to.equal(1);
+  expect(multisig.signers[0].toString()).to.equal(admin.publicKey.toString());
+});
+
+it("Initialize event counter", async () => {
+  [eventCounter] = PublicKey.findProgramAddressSync(
+    [Buffer.from("event_counter")],
+    program.programId
+  );
+  [eventAuthority] = PublicKey.findProgramAddressSync(
+    [Buffer.from("__event_authority")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeEventCounter()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      eventCounter,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const counter = await program.account.eventCounter.fetch(eventCounter);
+  expect(counter.seq.toNumber()).to.equal(0);
+});
+
+it("Update protocol fee", async () => {
+  const updateFee = async (depositFeeBps: number, withdrawalFeeBps: number) => {
+    const multisig = await program.account.adminMultisig.fetch(adminMultisig);
+    const actionId = multisig.actionCount.toArrayLike(Buffer, "le", 8);
+    const [pendingAdminAction] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending_admin_action"), actionId],
+      program.programId
+    );
+    const [scheduledChange] = PublicKey.findProgramAddressSync(
+      [Buffer.from("scheduled_change"), actionId],
+      program.programId
+    );
+
+    await program.methods
+      .proposeAdminAction({ updateProtocolFee: { depositFeeBps, withdrawalFeeBps } })
+      .accounts({
+        proposer: admin.publicKey,
+        adminMultisig,
+        pendingAdminAction,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    await program.methods
+      .scheduleChange()
+      .accounts({
+        authority: admin.publicKey,
+        protocolConfig,
+        adminMultisig,
+        pendingAdminAction,
+        scheduledChange,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+    await program.methods
+      .updateProtocolFee(depositFeeBps, withdrawalFeeBps)
+      .accounts({
+        protocolConfig,
+        scheduledChange,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+  };
+
+  await updateFee(250, 75);
+  let config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.depositFeeBps).to.equal(250);
+  expect(config.withdrawalFeeBps).to.equal(75);
+
+  // Restore the original fees for the remaining tests
+  await updateFee(DEPOSIT_FEE_BPS, WITHDRAWAL_FEE_BPS);
+  config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.depositFeeBps).to.equal(DEPOSIT_FEE_BPS);
+  expect(config.withdrawalFeeBps).to.equal(WITHDRAWAL_FEE_BPS);
+});
+
+it("Initialize fee schedule", async () => {
+  [feeSchedule] = PublicKey.findProgramAddressSync(
+    [Buffer.from("fee_schedule")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeFeeSchedule()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      feeSchedule,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const schedule = await program.account.feeSchedule.fetch(feeSchedule);
+  expect(schedule.tiers).to.be.empty;
+});
+
+it("Initialize withdrawal queue", async () => {
+  const [withdrawalQueue] = PublicKey.findProgramAddressSync(
+    [Buffer.from("withdrawal_queue")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeWithdrawalQueue(25)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      withdrawalQueue,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const queue = await program.account.withdrawalQueue.fetch(withdrawalQueue);
+  expect(queue.nextSequence.toNumber()).to.equal(0);
+  expect(queue.expressFeeBps).to.equal(25);
+});
+
+it("Initialize swap config", async () => {
+  const [swapConfig] = PublicKey.findProgramAddressSync(
+    [Buffer.from("swap_config")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeSwapConfig(30)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      swapConfig,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  let config = await program.account.swapConfig.fetch(swapConfig);
+  expect(config.swapAdapter).to.be.null;
+  expect(config.swapFeeBps).to.equal(30);
+
+  const swapAdapter = Keypair.generate().publicKey;
+  await program.methods
+    .setSwapConfig(swapAdapter, 25)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      swapConfig,
+    })
+    .signers([admin])
+    .rpc();
+
+  config = await program.account.swapConfig.fetch(swapConfig);
+  expect(config.swapAdapter.toString()).to.equal(swapAdapter.toString());
+  expect(config.swapFeeBps).to.equal(25);
+});
+
+it("Set savings APY", async () => {
+  const before = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(before.savingsApyBps).to.equal(0);
+
+  await program.methods
+    .setSavingsApy(450)
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.savingsApyBps).to.equal(450);
+  // Nothing accrued yet at a 0% APY, so the index is still 1.0
+  expect(config.savingsIndex.toString()).to.equal("1000000000000");
+});
+
+it("Set term deposit config", async () => {
+  const [termDepositConfig] = PublicKey.findProgramAddressSync(
+    [Buffer.from("term_deposit_config")],
+    program.programId
+  );
+
+  await program.methods
+    .setTermDepositConfig(
+      [
+        { termDays: 30, rateBps: 300 },
+        { termDays: 90, rateBps: 400 },
+      ],
+      100
+    )
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      termDepositConfig,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const config = await program.account.termDepositConfig.fetch(termDepositConfig);
+  expect(config.rates.map((rate) => rate.termDays)).to.deep.equal([30, 90]);
+  expect(config.earlyWithdrawalPenaltyBps).to.equal(100);
+
+  try {
+    await program.methods
+      .setTermDepositConfig([{ termDays: 90, rateBps: 400 }, { termDays: 30, rateBps: 300 }], 100)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        termDepositConfig,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+    assert.fail("unsorted terms should be rejected");
+  } catch (err) {
+    expect(err).to.be.instanceOf(AnchorError);
+    expect((err as AnchorError).error.errorCode.code).to.equal("InvalidTermRates");
+  }
+});
+
+it("Initialize staking pool", async () => {
+  const stakingMint = await createMint(
+    provider.connection,
+    admin,
+    admin.publicKey,
+    null,
+    6,
+    undefined,
+    { commitment: 'confirmed' }
+  );
+  const [stakingPool] = PublicKey.findProgramAddressSync(
+    [Buffer.from("staking_pool")],
+    program.programId
+  );
+  const [stakeVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("stake_vault")],
+    program.programId
+  );
+  const [rewardVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("staking_reward_vault")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeStakingPool()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      stakingMint,
+      rewardMint: mint,
+      stakingPool,
+      stakeVault,
+      rewardVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const pool = await program.account.stakingPool.fetch(stakingPool);
+  expect(pool.stakingMint.toString()).to.equal(stakingMint.toString());
+  expect(pool.rewardMint.toString()).to.equal(mint.toString());
+  expect(pool.totalStaked.toNumber()).to.equal(0);
+
+  const config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.stakingMint.toString()).to.equal(stakingMint.toString());
+});
+
+it("Initialize protocol stats", async () => {
+  [protocolStats] = PublicKey.findProgramAddressSync(
+    [Buffer.from("protocol_stats")],
+    program.programId
+  );
+
+  await program.methods
+    .initializeProtocolStats()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      protocolStats,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const stats = await program.account.protocolStats.fetch(protocolStats);
+  expect(stats.depositCount.toNumber()).to.equal(0);
+  expect(stats.totalDeposited.toNumber()).to.equal(0);
+  expect(stats.userCount.toNumber()).to.equal(0);
+  expect(stats.mintTvl).to.be.empty;
+});
+
+it("Grant and revoke operator role", async () => {
+  const operator = Keypair.generate();
+  const [operatorRole] = PublicKey.findProgramAddressSync(
+    [Buffer.from("operator_role"), operator.publicKey.toBuffer()],
+    program.programId
+  );
+
+  const completeDeposits = 1 << 0;
+  const completeWithdrawals = 1 << 1;
+
+  await program.methods
+    .grantRole(completeDeposits | completeWithdrawals)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      operator: operator.publicKey,
+      operatorRole,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  let role = await program.account.operatorRole.fetch(operatorRole);
+  expect(role.operator.toString()).to.equal(operator.publicKey.toString());
+  expect(role.permissions).to.equal(completeDeposits | completeWithdrawals);
+
+  await program.methods
+    .revokeRole(completeWithdrawals)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      operatorRole,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  role = await program.account.operatorRole.fetch(operatorRole);
+  expect(role.permissions).to.equal(completeDeposits);
+});
+
+it("Pause and unpause protocol", async () => {
+  await program.methods
+    .pauseProtocol()
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  let config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.isPaused).to.be.true;
+
+  await program.methods
+    .unpauseProtocol()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.isPaused).to.be.false;
 });
 
 it("Create user profile", async () => {
@@ -97,6 +550,10 @@ it("Create user profile", async () => {
     [Buffer.from("user_profile"), user.publicKey.toBuffer()],
     program.programId
   );
+  [userTxIndex] = PublicKey.findProgramAddressSync(
+    [Buffer.from("user_tx_index"), user.publicKey.toBuffer()],
+    program.programId
+  );
 
   const email = "test@example.com";
   const name = "Test User";
@@ -105,8 +562,14 @@ it("Create user profile", async () => {
     .createUserProfile(name, email)
     .accounts({
       user: user.publicKey,
+      userBlacklist,
       userProfile,
+      userTxIndex,
       systemProgram: SystemProgram.programId,
+      protocolStats,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
     })
     .signers([user])
     .rpc();
@@ -115,6 +578,45 @@ it("Create user profile", async () => {
   expect(profile.owner.toString()).to.equal(user.publicKey.toString());
   expect(profile.email).to.equal(email);
   expect(profile.name).to.equal(name);
+
+  const txIndex = await program.account.userTxIndex.fetch(userTxIndex);
+  expect(txIndex.owner.toString()).to.equal(user.publicKey.toString());
+  expect(txIndex.count.toNumber()).to.equal(0);
+
+  const stats = await program.account.protocolStats.fetch(protocolStats);
+  expect(stats.userCount.toNumber()).to.equal(1);
+});
+
+it("Set KYC status", async () => {
+  await program.methods
+    .setKycVerifier(kycVerifier.publicKey)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+    })
+    .signers([admin])
+    .rpc();
+
+  await program.methods
+    .setKycStatus({ basic: {} })
+    .accounts({
+      kycVerifier: kycVerifier.publicKey,
+      protocolConfig,
+      userProfile,
+      badgeAuthority: null,
+      badgeMint: null,
+      userBadgeAccount: null,
+      tokenProgram: null,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([kycVerifier])
+    .rpc();
+
+  const profile = await program.account.userProfile.fetch(userProfile);
+  expect(Object.keys(profile.kycTier)[0]).to.equal('basic');
+  expect(profile.kycVerifier.toString()).to.equal(kycVerifier.publicKey.toString());
 });
 
 it("Create vault", async () => {
@@ -127,31 +629,65 @@ it("Create vault", async () => {
     .createVault()
     .accounts({
       user: user.publicKey,
+      userBlacklist,
       userProfile,
       vault,
       systemProgram: SystemProgram.programId,
+      protocolStats,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
     })
     .signers([user])
     .rpc();
 
   const vaultData = await program.account.vault.fetch(vault);
   expect(vaultData.owner.toString()).to.equal(user.publicKey.toString());
+
+  const stats = await program.account.protocolStats.fetch(protocolStats);
+  expect(stats.vaultCount.toNumber()).to.equal(1);
 });
 
-it("Whitelist token", async () => {
+it("Propose and approve token", async () => {
+  const [proposedToken] = await PublicKey.findProgramAddress(
+    [Buffer.from("proposed_token"), mint.toBuffer()],
+    program.programId
+  );
   [tokenWhitelist] = await PublicKey.findProgramAddress(
     [Buffer.from("token_whitelist"), mint.toBuffer()],
     program.programId
   );
 
   await program.methods
-    .whitelistToken("USDC", "USD Coin", true)
+    .proposeToken("USDC", "USD Coin", true)
     .accounts({
-      admin: admin.publicKey,
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      mint,
+      proposedToken,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  await program.methods
+    .approveToken()
+    .accounts({
+      authority: admin.publicKey,
       protocolConfig,
+      scheduledChange: null,
+      proposedToken,
+      proposer: admin.publicKey,
       mint,
       tokenWhitelist,
       systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
     })
     .signers([admin])
     .rpc();
@@ -162,15 +698,143 @@ it("Whitelist token", async () => {
   expect(whitelistData.isActive).to.be.true;
 });
 
-it("Initiate and complete fiat deposit", async () => {
-  const amount = new anchor.BN(1000000); // 1 USDC
-  const referenceId = "TEST-DEP-001";
+it("Set token credit params", async () => {
+  await program.methods
+    .setTokenCreditParams(8000, 9000, 500, 500)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      tokenWhitelist,
+    })
+    .signers([admin])
+    .rpc();
 
-  const [fiatDeposit] = await PublicKey.findProgramAddress(
-    [
-      Buffer.from("fiat_deposit"),
-      user.publicKey.toBuffer(),
-      Buffer.from(referenceId)
+  const whitelistData = await program.account.tokenWhitelist.fetch(tokenWhitelist);
+  expect(whitelistData.maxLtvBps).to.equal(8000);
+  expect(whitelistData.liquidationLtvBps).to.equal(9000);
+  expect(whitelistData.borrowAprBps).to.equal(500);
+  expect(whitelistData.liquidationPenaltyBps).to.equal(500);
+
+  try {
+    await program.methods
+      .setTokenCreditParams(9500, 9000, 500, 500)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        tokenWhitelist,
+      })
+      .signers([admin])
+      .rpc();
+    assert.fail("max LTV above liquidation LTV should be rejected");
+  } catch (err) {
+    expect(err).to.be.instanceOf(AnchorError);
+    expect((err as AnchorError).error.errorCode.code).to.equal("InvalidCreditParams");
+  }
+});
+
+it("Set FX pair and quote a rate", async () => {
+  const eurMint = await createMint(
+    provider.connection,
+    admin,
+    admin.publicKey,
+    null,
+    6,
+    undefined,
+    { commitment: 'confirmed' }
+  );
+  const [fxPair] = PublicKey.findProgramAddressSync(
+    [Buffer.from("fx_pair"), eurMint.toBuffer(), mint.toBuffer()],
+    program.programId
+  );
+
+  await program.methods
+    .setFxPair({ quoted: {} }, 20, new anchor.BN(300), true)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      fromMint: eurMint,
+      toMint: mint,
+      fxPair,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const rate = new anchor.BN(1_080_000_000); // 1 EUR = 1.08 USD
+  await program.methods
+    .quoteFxRate(rate)
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fxPair,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const pair = await program.account.fxPair.fetch(fxPair);
+  expect(pair.spreadBps).to.equal(20);
+  expect(pair.quotedRate.toString()).to.equal(rate.toString());
+  expect(pair.rateSource).to.deep.equal({ quoted: {} });
+});
+
+it("Initialize treasury", async () => {
+  const [treasurySigner] = PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury_signer")],
+    program.programId
+  );
+  [treasury] = PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury"), mint.toBuffer()],
+    program.programId
+  );
+
+  treasuryTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    treasurySigner,
+    Keypair.generate()
+  );
+
+  await program.methods
+    .initializeTreasury()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      mint,
+      treasurySigner,
+      treasuryTokenAccount,
+      treasury,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const treasuryData = await program.account.treasury.fetch(treasury);
+  expect(treasuryData.mint.toString()).to.equal(mint.toString());
+  expect(treasuryData.tokenAccount.toString()).to.equal(treasuryTokenAccount.toString());
+});
+
+it("Initiate and complete fiat deposit", async () => {
+  const amount = new anchor.BN(1000000); // 1 USDC
+  const referenceId = "TEST-DEP-001";
+
+  const [fiatDeposit] = await PublicKey.findProgramAddress(
+    [
+      Buffer.from("fiat_deposit"),
+      user.publicKey.toBuffer(),
+      Buffer.from(referenceId)
+    ],
+    program.programId
+  );
+
+  const [referenceRegistry] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("reference_registry"),
+      createHash("sha256").update(referenceId).digest()
     ],
     program.programId
   );
@@ -183,25 +847,27 @@ it("Initiate and complete fiat deposit", async () => {
     user.publicKey
   );
 
-  treasuryTokenAccount = await createAccount(
-    provider.connection,
-    admin,
-    mint,
-    admin.publicKey
-  );
-
   await program.methods
     .initiateFiatDeposit(amount, referenceId)
     .accounts({
       user: user.publicKey,
+      userBlacklist,
+      protocolConfig,
       userProfile,
       mint,
       tokenWhitelist,
+      userLimits,
       fiatDeposit,
+      referenceRegistry,
       userTokenAccount,
+      treasury,
       treasuryTokenAccount,
       tokenProgram: TOKEN_PROGRAM_ID,
       systemProgram: SystemProgram.programId,
+      userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
     })
     .signers([user])
     .rpc();
@@ -214,6 +880,80 @@ it("Initiate and complete fiat deposit", async () => {
   expect(depositData.amount.toString()).to.equal(amount.toString());
   expect(depositData.referenceId).to.equal(referenceId);
   expect(depositData.user.toString()).to.equal(user.publicKey.toString());
+
+  // The reference is now claimed protocol-wide by this deposit
+  const registryData = await program.account.referenceRegistry.fetch(referenceRegistry);
+  expect(registryData.record.toString()).to.equal(fiatDeposit.toString());
+
+  // The deposit counts against the user's rolling limits
+  const limitsData = await program.account.userLimits.fetch(userLimits);
+  const dailyTotal = limitsData.hourlyDeposits.reduce((total, bucket) => total.add(bucket), new anchor.BN(0));
+  expect(dailyTotal.toString()).to.equal(amount.toString());
+
+  // The deposit shows up in the user's recent activity
+  const txIndex = await program.account.userTxIndex.fetch(userTxIndex);
+  expect(txIndex.count.toNumber()).to.equal(1);
+  expect(txIndex.entries[0].kind).to.equal(0); // TX_KIND_DEPOSIT
+  expect(txIndex.entries[0].amount.toString()).to.equal(amount.toString());
+  expect(txIndex.entries[0].mint.toString()).to.equal(mint.toString());
+});
+
+it("Record balance snapshot and prove a balance", async () => {
+  const epoch = new anchor.BN(1);
+  const [balanceSnapshot] = PublicKey.findProgramAddressSync(
+    [Buffer.from("balance_snapshot"), epoch.toArrayLike(Buffer, "le", 8)],
+    program.programId
+  );
+
+  // Two-leaf sorted-pair tree: sha256(0x00 || owner || mint || amount_le) leaves, 0x01 inner nodes
+  const leaf = (owner: PublicKey, amount: anchor.BN) =>
+    createHash("sha256")
+      .update(Buffer.from([0]))
+      .update(owner.toBuffer())
+      .update(mint.toBuffer())
+      .update(amount.toArrayLike(Buffer, "le", 8))
+      .digest();
+  const amount = new anchor.BN(1_000_000);
+  const userLeaf = leaf(user.publicKey, amount);
+  const otherLeaf = leaf(Keypair.generate().publicKey, new anchor.BN(5));
+  const [left, right] = Buffer.compare(userLeaf, otherLeaf) <= 0 ? [userLeaf, otherLeaf] : [otherLeaf, userLeaf];
+  const root = createHash("sha256").update(Buffer.from([1])).update(left).update(right).digest();
+
+  const slot = await provider.connection.getSlot();
+  await program.methods
+    .recordBalanceSnapshot(epoch, [...root], new anchor.BN(2), new anchor.BN(slot))
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      balanceSnapshot,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const snapshot = await program.account.balanceSnapshot.fetch(balanceSnapshot);
+  expect(Buffer.from(snapshot.merkleRoot).equals(root)).to.be.true;
+  expect(snapshot.leafCount.toNumber()).to.equal(2);
+
+  await program.methods
+    .proveBalance(user.publicKey, mint, amount, [[...otherLeaf]])
+    .accounts({ balanceSnapshot })
+    .rpc();
+
+  try {
+    await program.methods
+      .proveBalance(user.publicKey, mint, amount.addn(1), [[...otherLeaf]])
+      .accounts({ balanceSnapshot })
+      .rpc();
+    assert.fail("proof for a different amount should not verify");
+  } catch (err) {
+    expect(err).to.be.instanceOf(AnchorError);
+    expect((err as AnchorError).error.errorCode.code).to.equal("InvalidBalanceProof");
+  }
 });
 
 it("Should validate admin fee basis points", async () => {
@@ -227,7 +967,4731 @@ it("Should validate admin fee basis points", async () => {
   
   // We can also check that our existing protocol has valid fee basis points
   const config = await program.account.protocolConfig.fetch(protocolConfig);
-  expect(config.adminFeeBasisPoints).to.be.lessThanOrEqual(maxFeeBasisPoints);
-  console.log(`Verified that actual protocol fee (${config.adminFeeBasisPoints} basis points) is <= max`);
+  expect(config.depositFeeBps).to.be.lessThanOrEqual(maxFeeBasisPoints);
+  expect(config.withdrawalFeeBps).to.be.lessThanOrEqual(maxFeeBasisPoints);
+  console.log(`Verified that actual protocol fee (${config.depositFeeBps} basis points) is <= max`);
+});
+
+// A user profile written before accounts carried a version byte, preloaded into the validator
+// from tests/fixtures/legacy_user_profile.json
+const LEGACY_PROFILE_OWNER = new PublicKey("6uVEsMzt2MMyv8YjK2V97c1DyjCoB54R8mVicH8PPsLZ");
+
+it("Migrate a legacy user profile in place", async () => {
+  const [legacyProfile] = PublicKey.findProgramAddressSync(
+    [Buffer.from("user_profile"), LEGACY_PROFILE_OWNER.toBuffer()],
+    program.programId
+  );
+  const legacySize = (await provider.connection.getAccountInfo(legacyProfile)).data.length;
+
+  const migrate = () =>
+    program.methods
+      .migrateUserProfile()
+      .accounts({
+        payer: admin.publicKey,
+        account: legacyProfile,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([admin])
+      .rpc();
+  await migrate();
+
+  // The account grows to the current layout and keeps everything the old one held
+  expect((await provider.connection.getAccountInfo(legacyProfile)).data.length).to.be.greaterThan(legacySize);
+  const profile = await program.account.userProfile.fetch(legacyProfile);
+  expect(profile.version).to.equal(1);
+  expect(profile.owner.toString()).to.equal(LEGACY_PROFILE_OWNER.toString());
+  expect(profile.name).to.equal("Legacy User");
+  expect(profile.email).to.equal("legacy@example.com");
+  expect(Object.keys(profile.kycTier)[0]).to.equal("basic");
+  expect(profile.createdAt.toNumber()).to.equal(1_700_000_000);
+
+  // Once migrated the account no longer parses as the legacy layout
+  try {
+    await migrate();
+    assert.fail("expected AccountNotMigratable");
+  } catch (err) {
+    expect(err).to.be.instanceOf(AnchorError);
+    expect((err as AnchorError).error.errorCode.code).to.equal("AccountNotMigratable");
+  }
+});
+
+// A KYC-verified user with a vault holding a token account for `mint`
+interface VaultUser {
+  keypair: Keypair;
+  userProfile: PublicKey;
+  userBlacklist: PublicKey;
+  userLimits: PublicKey;
+  userTxIndex: PublicKey;
+  vault: PublicKey;
+  vaultTokenAccount: PublicKey;
+  payoutDestination: PublicKey;
+}
+
+const findPda = (...seeds: Buffer[]) =>
+  PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+
+const [treasurySigner] = PublicKey.findProgramAddressSync(
+  [Buffer.from("treasury_signer")],
+  program.programId
+);
+
+const createVaultTokenAccount = async (vaultUser: VaultUser, tokenMint: PublicKey) => {
+  const vaultTokenAccount = getAssociatedTokenAddressSync(tokenMint, vaultUser.vault, true);
+  await program.methods
+    .createVaultTokenAccount()
+    .accounts({
+      payer: vaultUser.keypair.publicKey,
+      vault: vaultUser.vault,
+      mint: tokenMint,
+      tokenWhitelist: findPda(Buffer.from("token_whitelist"), tokenMint.toBuffer()),
+      vaultTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+  return vaultTokenAccount;
+};
+
+const registerPayoutDestination = async (vaultUser: VaultUser) => {
+  const destinationHash = createHash("sha256").update(vaultUser.keypair.publicKey.toBase58()).digest();
+  const payoutDestination = findPda(
+    Buffer.from("payout_destination"),
+    vaultUser.keypair.publicKey.toBuffer(),
+    destinationHash
+  );
+  await program.methods
+    .registerPayoutDestination([...destinationHash])
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      protocolConfig,
+      payoutDestination,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+  return payoutDestination;
+};
+
+const onboardUser = async (name: string): Promise<VaultUser> => {
+  const keypair = Keypair.generate();
+  const airdrop = await provider.connection.requestAirdrop(
+    keypair.publicKey,
+    2 * anchor.web3.LAMPORTS_PER_SOL
+  );
+  await provider.connection.confirmTransaction(airdrop);
+
+  const owner = keypair.publicKey.toBuffer();
+  const vaultUser: VaultUser = {
+    keypair,
+    userProfile: findPda(Buffer.from("user_profile"), owner),
+    userBlacklist: findPda(Buffer.from("blacklist"), owner),
+    userLimits: findPda(Buffer.from("user_limits"), owner),
+    userTxIndex: findPda(Buffer.from("user_tx_index"), owner),
+    vault: findPda(Buffer.from("vault"), owner),
+    vaultTokenAccount: PublicKey.default,
+    payoutDestination: PublicKey.default,
+  };
+
+  await program.methods
+    .createUserProfile(name, `${name.toLowerCase()}@example.com`)
+    .accounts({
+      user: keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      userTxIndex: vaultUser.userTxIndex,
+      systemProgram: SystemProgram.programId,
+      protocolStats,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([keypair])
+    .rpc();
+
+  await program.methods
+    .setKycStatus({ basic: {} })
+    .accounts({
+      kycVerifier: kycVerifier.publicKey,
+      protocolConfig,
+      userProfile: vaultUser.userProfile,
+      badgeAuthority: null,
+      badgeMint: null,
+      userBadgeAccount: null,
+      tokenProgram: null,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([kycVerifier])
+    .rpc();
+
+  await program.methods
+    .createVault()
+    .accounts({
+      user: keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      vault: vaultUser.vault,
+      systemProgram: SystemProgram.programId,
+      protocolStats,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([keypair])
+    .rpc();
+
+  vaultUser.vaultTokenAccount = await createVaultTokenAccount(vaultUser, mint);
+  vaultUser.payoutDestination = await registerPayoutDestination(vaultUser);
+  return vaultUser;
+};
+
+// The admin holds the test mint's authority, so vaults are funded by minting straight into them
+const fundVault = (vaultUser: VaultUser, amount: number) =>
+  mintTo(provider.connection, admin, mint, vaultUser.vaultTokenAccount, admin, amount);
+
+const tokenBalance = async (tokenAccount: PublicKey) =>
+  Number((await getAccount(provider.connection, tokenAccount)).amount);
+
+const expectAnchorError = async (request: Promise<unknown>, code: string) => {
+  try {
+    await request;
+    assert.fail(`expected ${code}`);
+  } catch (err) {
+    expect(err).to.be.instanceOf(AnchorError);
+    expect((err as AnchorError).error.errorCode.code).to.equal(code);
+  }
+};
+
+const chainTime = async () =>
+  (await provider.connection.getBlockTime(await provider.connection.getSlot())) as number;
+
+// Waits for the validator clock, which can lag the wall clock, to pass `timestamp`
+const waitForChainTime = async (timestamp: number) => {
+  while ((await chainTime()) <= timestamp) {
+    await new Promise((resolve) => setTimeout(resolve, 500));
+  }
+};
+
+const fundedKeypair = async () => {
+  const keypair = Keypair.generate();
+  const airdrop = await provider.connection.requestAirdrop(
+    keypair.publicKey,
+    anchor.web3.LAMPORTS_PER_SOL
+  );
+  await provider.connection.confirmTransaction(airdrop);
+  return keypair;
+};
+
+const escrowAuthority = findPda(Buffer.from("withdrawal_escrow_authority"));
+const withdrawalEscrow = () => findPda(Buffer.from("withdrawal_escrow"), mint.toBuffer());
+
+// Completions carry an operator-chosen id, so a retried completion is recognised
+const randomOperationId = () => [...Keypair.generate().publicKey.toBytes().subarray(0, 16)];
+
+const withdrawalStatus = async (fiatWithdrawal: PublicKey) =>
+  Object.keys((await program.account.fiatWithdrawal.fetch(fiatWithdrawal)).status)[0];
+
+// A fresh reference for the next withdrawal out of `vaultUser`'s vault, with the accounts it seeds
+let vaultWithdrawalCount = 0;
+const nextWithdrawalReference = (vaultUser: VaultUser) => {
+  vaultWithdrawalCount += 1;
+  const referenceId = `TEST-VAULT-WD-${vaultWithdrawalCount}`;
+  return {
+    referenceId,
+    fiatWithdrawal: findPda(
+      Buffer.from("fiat_withdrawal"),
+      vaultUser.keypair.publicKey.toBuffer(),
+      mint.toBuffer(),
+      Buffer.from(referenceId)
+    ),
+    referenceRegistry: findPda(
+      Buffer.from("reference_registry"),
+      createHash("sha256").update(referenceId).digest()
+    ),
+  };
+};
+
+// Escrows `amount` from the vault into a pending withdrawal and returns the withdrawal's address
+const initiateFiatWithdrawal = async (
+  vaultUser: VaultUser,
+  amount: number,
+  {
+    remittanceQuote = null,
+    coSigner = null,
+  }: { remittanceQuote?: PublicKey; coSigner?: Keypair } = {}
+) => {
+  const { referenceId, fiatWithdrawal, referenceRegistry } = nextWithdrawalReference(vaultUser);
+  await program.methods
+    .initiateFiatWithdrawal(new anchor.BN(amount), referenceId)
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      protocolConfig,
+      userProfile: vaultUser.userProfile,
+      coSigner: coSigner?.publicKey ?? null,
+      vault: vaultUser.vault,
+      tokenWhitelist,
+      mint,
+      priceFeed: null,
+      payoutDestination: vaultUser.payoutDestination,
+      remittanceQuote,
+      userLimits: vaultUser.userLimits,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      treasury,
+      withdrawalQueue: findPda(Buffer.from("withdrawal_queue")),
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      fiatWithdrawal,
+      referenceRegistry,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers(coSigner ? [vaultUser.keypair, coSigner] : [vaultUser.keypair])
+    .rpc();
+  return fiatWithdrawal;
+};
+
+const completeFiatWithdrawal = (
+  vaultUser: VaultUser,
+  fiatWithdrawal: PublicKey,
+  authority: Keypair = admin,
+  operationId: number[] = randomOperationId()
+) =>
+  program.methods
+    .completeFiatWithdrawal(operationId)
+    .accounts({
+      authority: authority.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fiatWithdrawal,
+      userProfile: vaultUser.userProfile,
+      vault: vaultUser.vault,
+      mint,
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      treasury,
+      treasurySigner,
+      treasuryTokenAccount,
+      feeVault,
+      feeVaultTokenAccount,
+      userStats: findPda(Buffer.from("user_stats"), vaultUser.keypair.publicKey.toBuffer()),
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([authority])
+    .rpc();
+
+// Settles the withdrawal back into the vault, as the operator rejecting it
+const rejectFiatWithdrawal = (
+  vaultUser: VaultUser,
+  fiatWithdrawal: PublicKey,
+  reasonCode: number,
+  authority: Keypair = admin
+) =>
+  program.methods
+    .rejectFiatWithdrawal(reasonCode)
+    .accounts({
+      authority: authority.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fiatWithdrawal,
+      userProfile: vaultUser.userProfile,
+      vault: vaultUser.vault,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      mint,
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([authority])
+    .rpc();
+
+it("Set the payout destination cooldown", async () => {
+  const setPayoutDestinationCooldown = (authority: Keypair, cooldown: number) =>
+    program.methods
+      .setPayoutDestinationCooldown(new anchor.BN(cooldown))
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+      })
+      .signers([authority])
+      .rpc();
+
+  expect((await program.account.protocolConfig.fetch(protocolConfig)).payoutDestinationCooldown.toNumber())
+    .to.equal(24 * 60 * 60);
+  await expectAnchorError(setPayoutDestinationCooldown(user, 0), "Unauthorized");
+  await expectAnchorError(setPayoutDestinationCooldown(admin, -1), "InvalidPayoutDestinationCooldown");
+
+  // The rest of the suite withdraws to destinations registered moments earlier
+  await setPayoutDestinationCooldown(admin, 0);
+  const config = await program.account.protocolConfig.fetch(protocolConfig);
+  expect(config.payoutDestinationCooldown.toNumber()).to.equal(0);
+});
+
+let feeVault: PublicKey;
+let feeVaultTokenAccount: PublicKey;
+
+const initializeFeeVault = async (tokenMint: PublicKey) => {
+  const accounts = {
+    feeVault: findPda(Buffer.from("fee_vault"), tokenMint.toBuffer()),
+    feeVaultTokenAccount: findPda(Buffer.from("fee_vault_token_account"), tokenMint.toBuffer()),
+  };
+  await program.methods
+    .initializeFeeVault()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      mint: tokenMint,
+      ...accounts,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+  return accounts;
+};
+
+it("Initialize the fee vault", async () => {
+  ({ feeVault, feeVaultTokenAccount } = await initializeFeeVault(mint));
+  const feeVaultData = await program.account.feeVault.fetch(feeVault);
+  expect(feeVaultData.mint.toBase58()).to.equal(mint.toBase58());
+  expect(feeVaultData.tokenAccount.toBase58()).to.equal(feeVaultTokenAccount.toBase58());
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(0);
+});
+
+const outstandingBalance = async (mintTreasury: PublicKey) =>
+  (await program.account.treasury.fetch(mintTreasury)).outstandingBalance.toNumber();
+
+// Credits a vault through a completed fiat deposit, so the treasury counts the balance as
+// outstanding. Returns the amount credited after the deposit fee
+let vaultDepositCount = 0;
+const initiateVaultDeposit = async (vaultUser: VaultUser, amount: number) => {
+  vaultDepositCount += 1;
+  const referenceId = `TEST-VAULT-DEP-${vaultDepositCount}`;
+  const fiatDeposit = findPda(
+    Buffer.from("fiat_deposit"),
+    vaultUser.keypair.publicKey.toBuffer(),
+    Buffer.from(referenceId)
+  );
+
+  await program.methods
+    .initiateFiatDeposit(new anchor.BN(amount), referenceId)
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      protocolConfig,
+      userProfile: vaultUser.userProfile,
+      mint,
+      tokenWhitelist,
+      userLimits: vaultUser.userLimits,
+      fiatDeposit,
+      referenceRegistry: findPda(
+        Buffer.from("reference_registry"),
+        createHash("sha256").update(referenceId).digest()
+      ),
+      userTokenAccount: vaultUser.vaultTokenAccount,
+      treasury,
+      treasuryTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+  return fiatDeposit;
+};
+
+const completeVaultDeposit = (
+  vaultUser: VaultUser,
+  fiatDeposit: PublicKey,
+  operationId: number[] = randomOperationId()
+) =>
+  program.methods
+    .completeFiatDeposit(operationId)
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fiatDeposit,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      feeSchedule,
+      vault: vaultUser.vault,
+      mint,
+      tokenWhitelist,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      treasury,
+      treasurySigner,
+      treasuryTokenAccount,
+      feeVault,
+      feeVaultTokenAccount,
+      userStats: findPda(Buffer.from("user_stats"), vaultUser.keypair.publicKey.toBuffer()),
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+const depositToVault = async (vaultUser: VaultUser, amount: number) => {
+  const fiatDeposit = await initiateVaultDeposit(vaultUser, amount);
+  await completeVaultDeposit(vaultUser, fiatDeposit);
+  return amount - Math.floor((amount * DEPOSIT_FEE_BPS) / 10000);
+};
+
+it("Deposits complete only while the treasury keeps its reserve ratio", async () => {
+  const setTreasuryReserveRatio = (authority: Keypair, reserveRatioBps: number) =>
+    program.methods
+      .setTreasuryReserveRatio(reserveRatioBps)
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+        treasury,
+      })
+      .signers([authority])
+      .rpc();
+
+  await expectAnchorError(setTreasuryReserveRatio(user, 10000), "Unauthorized");
+  await expectAnchorError(setTreasuryReserveRatio(admin, 10001), "InvalidReserveRatio");
+  await setTreasuryReserveRatio(admin, 10000);
+
+  // Top the reserves up to exactly what one fully backed deposit of `amount` needs
+  const amount = 1_000_000;
+  const reserves = await tokenBalance(treasuryTokenAccount);
+  const outstanding = await outstandingBalance(treasury);
+  await mintTo(
+    provider.connection,
+    admin,
+    mint,
+    treasuryTokenAccount,
+    admin,
+    outstanding + 2 * amount - reserves
+  );
+
+  const depositor = await onboardUser("Reserve Depositor");
+  const credited = await depositToVault(depositor, amount);
+  expect(await outstandingBalance(treasury)).to.equal(outstanding + credited);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(outstanding + amount);
+
+  // A second deposit would leave less in the treasury than users now hold
+  const fiatDeposit = await initiateVaultDeposit(depositor, amount);
+  await expectAnchorError(completeVaultDeposit(depositor, fiatDeposit), "ReserveRatioBreached");
+
+  await setTreasuryReserveRatio(admin, 0);
+  await completeVaultDeposit(depositor, fiatDeposit);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(2 * credited);
+});
+
+it("Fund treasury reserves and credit a vault deposit", async () => {
+  // Completed deposits are paid out of reserves the treasury already holds
+  await mintTo(provider.connection, admin, mint, treasuryTokenAccount, admin, 1_000_000_000);
+
+  const saver = await onboardUser("Depositor");
+  const outstandingBefore = await outstandingBalance(treasury);
+  const feesBefore = await tokenBalance(feeVaultTokenAccount);
+  const credited = await depositToVault(saver, 2_000_000);
+
+  expect(credited).to.equal(1_980_000);
+  expect(await tokenBalance(saver.vaultTokenAccount)).to.equal(credited);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(feesBefore + 20_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore + credited);
+});
+
+it("Admin claims accrued fees out of the fee vault", async () => {
+  const claimFees = (authority: Keypair, adminTokenAccount: PublicKey, amount: number) =>
+    program.methods
+      .claimFees(new anchor.BN(amount))
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+        feeVault,
+        mint,
+        feeVaultTokenAccount,
+        adminTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([authority])
+      .rpc();
+
+  const adminTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    admin.publicKey,
+    Keypair.generate()
+  );
+  const before = await program.account.feeVault.fetch(feeVault);
+  const unclaimed = before.totalAccrued.toNumber() - before.totalClaimed.toNumber();
+  expect(unclaimed).to.be.greaterThan(0);
+
+  await expectAnchorError(claimFees(user, adminTokenAccount, 1), "Unauthorized");
+  await expectAnchorError(claimFees(admin, adminTokenAccount, unclaimed + 1), "InsufficientFunds");
+
+  // Fees can only be paid to a token account the admin owns
+  const userTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    user.publicKey,
+    Keypair.generate()
+  );
+  await expectAnchorError(claimFees(admin, userTokenAccount, 1), "InvalidTokenAccountOwner");
+
+  const vaultBalance = await tokenBalance(feeVaultTokenAccount);
+  await claimFees(admin, adminTokenAccount, unclaimed);
+  expect(await tokenBalance(adminTokenAccount)).to.equal(unclaimed);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(vaultBalance - unclaimed);
+  const after = await program.account.feeVault.fetch(feeVault);
+  expect(after.totalClaimed.toNumber()).to.equal(before.totalAccrued.toNumber());
+});
+
+before(async () => {
+  // Treasury withdrawals are signed and paid for by the treasury authority
+  const airdrop = await provider.connection.requestAirdrop(
+    treasuryAuthority.publicKey,
+    anchor.web3.LAMPORTS_PER_SOL
+  );
+  await provider.connection.confirmTransaction(airdrop);
+});
+
+const queueTreasuryWithdrawal = async (authority: Keypair, destination: PublicKey, amount: number) => {
+  const { withdrawalCount } = await program.account.treasury.fetch(treasury);
+  const treasuryWithdrawal = findPda(
+    Buffer.from("treasury_withdrawal"),
+    treasury.toBuffer(),
+    withdrawalCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .queueTreasuryWithdrawal(new anchor.BN(amount))
+    .accounts({
+      treasuryAuthority: authority.publicKey,
+      protocolConfig,
+      treasury,
+      destination,
+      treasuryWithdrawal,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([authority])
+    .rpc();
+  return treasuryWithdrawal;
+};
+
+it("Treasury withdrawals wait out their delay and can be cancelled", async () => {
+  const destination = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    treasuryAuthority.publicKey,
+    Keypair.generate()
+  );
+
+  // Only the treasury authority moves reserves, not the admin
+  await expectAnchorError(queueTreasuryWithdrawal(admin, destination, 1_000), "Unauthorized");
+  await expectAnchorError(queueTreasuryWithdrawal(treasuryAuthority, destination, 0), "InvalidAmount");
+
+  const treasuryWithdrawal = await queueTreasuryWithdrawal(treasuryAuthority, destination, 1_000);
+  const queued = await program.account.treasuryWithdrawal.fetch(treasuryWithdrawal);
+  expect(queued.destination.toBase58()).to.equal(destination.toBase58());
+  expect(queued.amount.toNumber()).to.equal(1_000);
+  expect(queued.executableAt.toNumber() - queued.queuedAt.toNumber()).to.equal(48 * 60 * 60);
+
+  await expectAnchorError(
+    program.methods
+      .executeTreasuryWithdrawal()
+      .accounts({
+        treasuryAuthority: treasuryAuthority.publicKey,
+        protocolConfig,
+        treasury,
+        treasuryWithdrawal,
+        mint,
+        treasurySigner,
+        treasuryTokenAccount,
+        destination,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([treasuryAuthority])
+      .rpc(),
+    "TimelockNotElapsed"
+  );
+
+  const reserves = await tokenBalance(treasuryTokenAccount);
+  await program.methods
+    .cancelTreasuryWithdrawal()
+    .accounts({
+      treasuryAuthority: treasuryAuthority.publicKey,
+      protocolConfig,
+      treasuryWithdrawal,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([treasuryAuthority])
+    .rpc();
+  expect(await provider.connection.getAccountInfo(treasuryWithdrawal)).to.be.null;
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reserves);
+  expect(await tokenBalance(destination)).to.equal(0);
+});
+
+// Deployed by Anchor.toml from programs/mock-yield-adapter
+const MOCK_YIELD_ADAPTER_ID = new PublicKey("ATits75xxDL6v9Er9Nb99cnDVD3yaGcEGJ8kt1MFmg7a");
+const [yieldPoolAuthority] = PublicKey.findProgramAddressSync(
+  [Buffer.from("pool")],
+  MOCK_YIELD_ADAPTER_ID
+);
+
+const setTreasuryYieldConfig = (authority: Keypair, yieldAdapter: PublicKey | null, maxDeploymentBps: number) =>
+  program.methods
+    .setTreasuryYieldConfig(yieldAdapter, maxDeploymentBps)
+    .accounts({
+      admin: authority.publicKey,
+      protocolConfig,
+      treasury,
+    })
+    .signers([authority])
+    .rpc();
+
+it("Deploy idle treasury reserves to yield and recall them with interest", async () => {
+  const poolTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    yieldPoolAuthority,
+    Keypair.generate()
+  );
+  const yieldAccounts = {
+    treasuryAuthority: treasuryAuthority.publicKey,
+    protocolConfig,
+    treasury,
+    mint,
+    treasurySigner,
+    treasuryTokenAccount,
+    yieldProgram: MOCK_YIELD_ADAPTER_ID,
+    tokenProgram: TOKEN_PROGRAM_ID,
+  };
+  const poolAccounts = [
+    { pubkey: poolTokenAccount, isSigner: false, isWritable: true },
+    { pubkey: yieldPoolAuthority, isSigner: false, isWritable: false },
+  ];
+  const deployToYield = (amount: number) =>
+    program.methods
+      .deployTreasuryToYield(new anchor.BN(amount))
+      .accounts({
+        ...yieldAccounts,
+      })
+      .remainingAccounts(poolAccounts)
+      .signers([treasuryAuthority])
+      .rpc();
+  const recallFromYield = (amount: number) =>
+    program.methods
+      .recallFromYield(new anchor.BN(amount))
+      .accounts({
+        ...yieldAccounts,
+      })
+      .remainingAccounts(poolAccounts)
+      .signers([treasuryAuthority])
+      .rpc();
+
+  await mintTo(provider.connection, admin, mint, treasuryTokenAccount, admin, 2_000_000);
+  const reserves = await tokenBalance(treasuryTokenAccount);
+  const deployed = Math.floor(reserves / 2);
+
+  await expectAnchorError(deployToYield(deployed), "YieldAdapterNotConfigured");
+  await expectAnchorError(setTreasuryYieldConfig(user, MOCK_YIELD_ADAPTER_ID, 5000), "Unauthorized");
+  await expectAnchorError(
+    setTreasuryYieldConfig(admin, MOCK_YIELD_ADAPTER_ID, 10001),
+    "InvalidDeploymentRatio"
+  );
+  await setTreasuryYieldConfig(admin, MOCK_YIELD_ADAPTER_ID, 5000);
+
+  await deployToYield(deployed);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reserves - deployed);
+  expect(await tokenBalance(poolTokenAccount)).to.equal(deployed);
+  expect((await program.account.treasury.fetch(treasury)).deployedAmount.toNumber()).to.equal(deployed);
+
+  // Half the reserves are already out, so the cap leaves nothing more to deploy
+  await expectAnchorError(deployToYield(1), "YieldDeploymentLimitExceeded");
+  // Nor can the adapter be swapped out from under funds still deployed through it
+  await expectAnchorError(setTreasuryYieldConfig(admin, null, 0), "YieldFundsDeployed");
+
+  // Yield accrued in the pool comes back with the principal
+  await mintTo(provider.connection, admin, mint, poolTokenAccount, admin, 10_000);
+  await recallFromYield(deployed + 10_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reserves + 10_000);
+  expect(await tokenBalance(poolTokenAccount)).to.equal(0);
+  expect((await program.account.treasury.fetch(treasury)).deployedAmount.toNumber()).to.equal(0);
+
+  await setTreasuryYieldConfig(admin, null, 0);
+});
+
+const treasuryShardAddress = (index: number) =>
+  findPda(Buffer.from("treasury_shard"), mint.toBuffer(), Buffer.from([index]));
+
+const createTreasuryShard = async (authority: Keypair) => {
+  const { shards } = await program.account.treasury.fetch(treasury);
+  const shardTokenAccount = treasuryShardAddress(shards.length);
+  await program.methods
+    .createTreasuryShard()
+    .accounts({
+      admin: authority.publicKey,
+      protocolConfig,
+      treasury,
+      mint,
+      treasurySigner,
+      shardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([authority])
+    .rpc();
+  return shardTokenAccount;
+};
+
+const rebalanceTreasuryShards = (source: PublicKey, destination: PublicKey, amount: number) =>
+  program.methods
+    .rebalanceTreasuryShards(new anchor.BN(amount))
+    .accounts({
+      treasuryAuthority: treasuryAuthority.publicKey,
+      protocolConfig,
+      treasury,
+      mint,
+      treasurySigner,
+      source,
+      destination,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([treasuryAuthority])
+    .rpc();
+
+it("Spread treasury reserves across shards and rebalance between them", async () => {
+  await expectAnchorError(createTreasuryShard(user), "Unauthorized");
+  const shard = await createTreasuryShard(admin);
+  const { shards } = await program.account.treasury.fetch(treasury);
+  expect(shards.map((key) => key.toBase58())).to.contain(shard.toBase58());
+
+  await mintTo(provider.connection, admin, mint, treasuryTokenAccount, admin, 1_000_000);
+  const reserves = await tokenBalance(treasuryTokenAccount);
+  await rebalanceTreasuryShards(treasuryTokenAccount, shard, 400_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reserves - 400_000);
+  expect(await tokenBalance(shard)).to.equal(400_000);
+
+  // Rebalancing only moves tokens between the treasury's own accounts
+  const outsider = await createAccount(provider.connection, admin, mint, admin.publicKey, Keypair.generate());
+  await expectAnchorError(rebalanceTreasuryShards(shard, outsider, 100_000), "InvalidTreasuryAccount");
+  await expectAnchorError(rebalanceTreasuryShards(shard, shard, 100_000), "InvalidTreasuryAccount");
+
+  await rebalanceTreasuryShards(shard, treasuryTokenAccount, 400_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reserves);
+  expect(await tokenBalance(shard)).to.equal(0);
+});
+
+const setWithdrawalTtl = (seconds: number) =>
+  program.methods
+    .setWithdrawalTtl(new anchor.BN(seconds))
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+    })
+    .signers([admin])
+    .rpc();
+
+// Returns an expired withdrawal to its vault; the crank is permissionless, so any `caller` may run it
+const refundExpiredWithdrawal = (vaultUser: VaultUser, fiatWithdrawal: PublicKey, caller: Keypair) =>
+  program.methods
+    .refundExpiredWithdrawal()
+    .accounts({
+      caller: caller.publicKey,
+      protocolConfig,
+      fiatWithdrawal,
+      vault: vaultUser.vault,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      mint,
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([caller])
+    .rpc();
+
+it("Complete a vault withdrawal net of the withdrawal fee", async () => {
+  const holder = await onboardUser("Withdrawing Holder");
+  await fundVault(holder, 2_000_000);
+  const feeAccount = feeVaultTokenAccount;
+  const payoutAccount = treasuryTokenAccount;
+  const feesBefore = await tokenBalance(feeAccount);
+  const payoutsBefore = await tokenBalance(payoutAccount);
+  const escrowBefore = await tokenBalance(withdrawalEscrow());
+
+  // Initiating escrows the amount out of the vault until the payout is settled
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 1_000_000);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+  expect(await tokenBalance(withdrawalEscrow())).to.equal(escrowBefore + 1_000_000);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("pending");
+
+  // Only the admin or an operator with the completion permission settles withdrawals
+  await expectAnchorError(completeFiatWithdrawal(holder, fiatWithdrawal, user), "Unauthorized");
+
+  await completeFiatWithdrawal(holder, fiatWithdrawal);
+
+  const fee = Math.floor((1_000_000 * WITHDRAWAL_FEE_BPS) / 10000);
+  const completed = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(Object.keys(completed.status)[0]).to.equal("completed");
+  expect(completed.feeAmount.toNumber()).to.equal(fee);
+  expect(completed.netAmount.toNumber()).to.equal(1_000_000 - fee);
+  expect(await tokenBalance(withdrawalEscrow())).to.equal(escrowBefore);
+  expect(await tokenBalance(feeAccount)).to.equal(feesBefore + fee);
+  expect(await tokenBalance(payoutAccount)).to.equal(payoutsBefore + 1_000_000 - fee);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+
+  // A settled withdrawal cannot be completed or rejected again
+  await expectAnchorError(completeFiatWithdrawal(holder, fiatWithdrawal), "InvalidWithdrawalStatus");
+  await expectAnchorError(rejectFiatWithdrawal(holder, fiatWithdrawal, 1), "InvalidWithdrawalStatus");
+});
+
+it("Reject a vault withdrawal back into the vault", async () => {
+  const holder = await onboardUser("Rejected Holder");
+  await fundVault(holder, 1_000_000);
+
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 600_000);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(400_000);
+
+  await expectAnchorError(rejectFiatWithdrawal(holder, fiatWithdrawal, 7, user), "Unauthorized");
+  await rejectFiatWithdrawal(holder, fiatWithdrawal, 7);
+
+  const rejected = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(Object.keys(rejected.status)[0]).to.equal("rejected");
+  expect(rejected.rejectionReason).to.equal(7);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+
+  await expectAnchorError(completeFiatWithdrawal(holder, fiatWithdrawal), "InvalidWithdrawalStatus");
+});
+
+it("Refund an expired vault withdrawal to the vault", async () => {
+  const holder = await onboardUser("Expiring Holder");
+  await fundVault(holder, 1_000_000);
+  const caller = await fundedKeypair();
+  await setWithdrawalTtl(10);
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 250_000);
+  await setWithdrawalTtl(7 * 24 * 60 * 60);
+
+  // Anyone may return an expired withdrawal to its vault, but not before it expires
+  await expectAnchorError(refundExpiredWithdrawal(holder, fiatWithdrawal, caller), "WithdrawalNotExpired");
+
+  const { expiresAt } = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  await waitForChainTime(expiresAt.toNumber());
+  await expectAnchorError(completeFiatWithdrawal(holder, fiatWithdrawal), "WithdrawalExpired");
+
+  await refundExpiredWithdrawal(holder, fiatWithdrawal, caller);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("expired");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+});
+
+// Cancels as the withdrawing user within the grace period, or as an operator at any time before settlement
+const cancelFiatWithdrawal = (vaultUser: VaultUser, fiatWithdrawal: PublicKey, authority: Keypair) =>
+  program.methods
+    .cancelFiatWithdrawal()
+    .accounts({
+      authority: authority.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fiatWithdrawal,
+      userProfile: vaultUser.userProfile,
+      vault: vaultUser.vault,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      mint,
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([authority])
+    .rpc();
+
+const markWithdrawalProcessing = (
+  vaultUser: VaultUser,
+  fiatWithdrawal: PublicKey,
+  authority: Keypair = admin
+) =>
+  program.methods
+    .markWithdrawalProcessing()
+    .accounts({
+      authority: authority.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fiatWithdrawal,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([authority])
+    .rpc();
+
+const setWithdrawalGracePeriod = (seconds: number) =>
+  program.methods
+    .setWithdrawalGracePeriod(new anchor.BN(seconds))
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+    })
+    .signers([admin])
+    .rpc();
+
+it("Users cancel their own withdrawal within the grace period", async () => {
+  const holder = await onboardUser("Cancelling Holder");
+  await fundVault(holder, 1_000_000);
+
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 300_000);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(700_000);
+
+  // Someone other than the withdrawing user needs the operator permission to cancel
+  const stranger = await fundedKeypair();
+  await expectAnchorError(cancelFiatWithdrawal(holder, fiatWithdrawal, stranger), "Unauthorized");
+
+  await cancelFiatWithdrawal(holder, fiatWithdrawal, holder.keypair);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("cancelled");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+
+  // With no grace period left the user can no longer back out, but an operator still can
+  await setWithdrawalGracePeriod(0);
+  const lateWithdrawal = await initiateFiatWithdrawal(holder, 300_000);
+  await setWithdrawalGracePeriod(60 * 60);
+  await expectAnchorError(
+    cancelFiatWithdrawal(holder, lateWithdrawal, holder.keypair),
+    "GracePeriodElapsed"
+  );
+  await cancelFiatWithdrawal(holder, lateWithdrawal, admin);
+  expect(await withdrawalStatus(lateWithdrawal)).to.equal("cancelled");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+});
+
+it("Withdrawals marked processing can no longer be cancelled by the user", async () => {
+  const holder = await onboardUser("Processing Holder");
+  await fundVault(holder, 1_000_000);
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 400_000);
+
+  await expectAnchorError(markWithdrawalProcessing(holder, fiatWithdrawal, user), "Unauthorized");
+  await markWithdrawalProcessing(holder, fiatWithdrawal);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("processing");
+
+  // The payout is in flight, so only one marking is allowed and the user's grace period no longer applies
+  await expectAnchorError(markWithdrawalProcessing(holder, fiatWithdrawal), "InvalidWithdrawalStatus");
+  await expectAnchorError(
+    cancelFiatWithdrawal(holder, fiatWithdrawal, holder.keypair),
+    "InvalidWithdrawalStatus"
+  );
+
+  await completeFiatWithdrawal(holder, fiatWithdrawal);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("completed");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(600_000);
+});
+
+it("Processing withdrawals never expire back into the vault", async () => {
+  const holder = await onboardUser("Slow Payout Holder");
+  await fundVault(holder, 1_000_000);
+  const caller = await fundedKeypair();
+  await setWithdrawalTtl(10);
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 500_000);
+  await setWithdrawalTtl(7 * 24 * 60 * 60);
+  await markWithdrawalProcessing(holder, fiatWithdrawal);
+
+  // Refunding after the payout was sent would pay the user twice, so the operator can still complete it
+  const { expiresAt } = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  await waitForChainTime(expiresAt.toNumber());
+  await expectAnchorError(
+    refundExpiredWithdrawal(holder, fiatWithdrawal, caller),
+    "InvalidWithdrawalStatus"
+  );
+
+  await completeFiatWithdrawal(holder, fiatWithdrawal);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("completed");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(500_000);
+});
+
+// Flattens per-withdrawal account groups into a batch instruction's remaining accounts
+const batchRemainingAccounts = (groups: PublicKey[][]) =>
+  ([] as PublicKey[])
+    .concat(...groups)
+    .map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }));
+
+const completeFiatWithdrawalsBatch = (
+  vaultUsers: VaultUser[],
+  fiatWithdrawals: PublicKey[],
+  {
+    authority = admin,
+    withdrawalCount = fiatWithdrawals.length,
+  }: { authority?: Keypair; withdrawalCount?: number } = {}
+) =>
+  program.methods
+    .completeFiatWithdrawalsBatch(randomOperationId(), withdrawalCount)
+    .accounts({
+      authority: authority.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      mint,
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      treasury,
+      treasurySigner,
+      treasuryTokenAccount,
+      feeVault,
+      feeVaultTokenAccount,
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .remainingAccounts(
+      batchRemainingAccounts(
+        fiatWithdrawals.map((fiatWithdrawal, i) => [
+          fiatWithdrawal,
+          vaultUsers[i].userProfile,
+          vaultUsers[i].vault,
+          findPda(Buffer.from("user_stats"), vaultUsers[i].keypair.publicKey.toBuffer()),
+          vaultUsers[i].userTxIndex,
+        ])
+      )
+    )
+    .signers([authority])
+    .rpc();
+
+const cancelFiatWithdrawalsBatch = (
+  vaultUsers: VaultUser[],
+  fiatWithdrawals: PublicKey[],
+  {
+    authority = admin,
+    withdrawalCount = fiatWithdrawals.length,
+  }: { authority?: Keypair; withdrawalCount?: number } = {}
+) =>
+  program.methods
+    .cancelFiatWithdrawalsBatch(withdrawalCount)
+    .accounts({
+      authority: authority.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      mint,
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      treasury,
+      treasurySigner,
+      treasuryTokenAccount,
+      feeVault,
+      feeVaultTokenAccount,
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .remainingAccounts(
+      batchRemainingAccounts(
+        fiatWithdrawals.map((fiatWithdrawal, i) => [
+          fiatWithdrawal,
+          vaultUsers[i].userProfile,
+          vaultUsers[i].vault,
+          vaultUsers[i].vaultTokenAccount,
+          vaultUsers[i].userTxIndex,
+        ])
+      )
+    )
+    .signers([authority])
+    .rpc();
+
+it("Complete and cancel withdrawals in batches", async () => {
+  const holders = [await onboardUser("Batch Holder A"), await onboardUser("Batch Holder B")];
+  for (const holder of holders) {
+    await fundVault(holder, 1_000_000);
+    // Batches update each user's stats account in place, so settle a first withdrawal singly to create it
+    await completeFiatWithdrawal(holder, await initiateFiatWithdrawal(holder, 100_000));
+  }
+  const feeAccount = feeVaultTokenAccount;
+  const payoutAccount = treasuryTokenAccount;
+  const feesBefore = await tokenBalance(feeAccount);
+  const payoutsBefore = await tokenBalance(payoutAccount);
+  const vaultsBefore = [
+    await tokenBalance(holders[0].vaultTokenAccount),
+    await tokenBalance(holders[1].vaultTokenAccount),
+  ];
+
+  const toComplete = [
+    await initiateFiatWithdrawal(holders[0], 400_000),
+    await initiateFiatWithdrawal(holders[1], 200_000),
+  ];
+  const toCancel = [
+    await initiateFiatWithdrawal(holders[0], 300_000),
+    await initiateFiatWithdrawal(holders[1], 250_000),
+  ];
+
+  await expectAnchorError(
+    completeFiatWithdrawalsBatch(holders, toComplete, { authority: user }),
+    "Unauthorized"
+  );
+  // The count tells the program where the withdrawal groups end, so it must match the accounts passed
+  await expectAnchorError(
+    completeFiatWithdrawalsBatch(holders, toComplete, { withdrawalCount: 3 }),
+    "InvalidBatchAccounts"
+  );
+
+  await completeFiatWithdrawalsBatch(holders, toComplete);
+  const fees = [
+    Math.floor((400_000 * WITHDRAWAL_FEE_BPS) / 10000),
+    Math.floor((200_000 * WITHDRAWAL_FEE_BPS) / 10000),
+  ];
+  expect(await withdrawalStatus(toComplete[0])).to.equal("completed");
+  expect(await withdrawalStatus(toComplete[1])).to.equal("completed");
+  expect(await tokenBalance(feeAccount)).to.equal(feesBefore + fees[0] + fees[1]);
+  expect(await tokenBalance(payoutAccount)).to.equal(
+    payoutsBefore + 600_000 - fees[0] - fees[1]
+  );
+
+  // One settled withdrawal fails the whole batch, leaving the other untouched
+  await expectAnchorError(
+    cancelFiatWithdrawalsBatch(holders, [toCancel[0], toComplete[1]]),
+    "InvalidWithdrawalStatus"
+  );
+  expect(await withdrawalStatus(toCancel[0])).to.equal("pending");
+
+  await cancelFiatWithdrawalsBatch(holders, toCancel);
+  expect(await withdrawalStatus(toCancel[0])).to.equal("cancelled");
+  expect(await withdrawalStatus(toCancel[1])).to.equal("cancelled");
+  expect(await tokenBalance(holders[0].vaultTokenAccount)).to.equal(vaultsBefore[0] - 400_000);
+  expect(await tokenBalance(holders[1].vaultTokenAccount)).to.equal(vaultsBefore[1] - 200_000);
+});
+
+it("Retried completions are no-ops and record the processing operator", async () => {
+  const holder = await onboardUser("Retried Holder");
+  await fundVault(holder, 1_000_000);
+  const fiatWithdrawal = await initiateFiatWithdrawal(holder, 500_000);
+
+  await expectAnchorError(
+    completeFiatWithdrawal(holder, fiatWithdrawal, admin, new Array(16).fill(0)),
+    "InvalidOperationId"
+  );
+
+  const operationId = randomOperationId();
+  await completeFiatWithdrawal(holder, fiatWithdrawal, admin, operationId);
+  const completed = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(completed.processedBy.toBase58()).to.equal(admin.publicKey.toBase58());
+  expect(completed.operationId).to.deep.equal(operationId);
+
+  // Retrying the same operation succeeds without paying out twice; a different operation is refused
+  const payoutsBefore = await tokenBalance(treasuryTokenAccount);
+  await completeFiatWithdrawal(holder, fiatWithdrawal, admin, operationId);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(payoutsBefore);
+  await expectAnchorError(completeFiatWithdrawal(holder, fiatWithdrawal), "InvalidWithdrawalStatus");
+
+  const fiatDeposit = await initiateVaultDeposit(holder, 300_000);
+  const depositOperationId = randomOperationId();
+  await completeVaultDeposit(holder, fiatDeposit, depositOperationId);
+  const vaultBalance = await tokenBalance(holder.vaultTokenAccount);
+  await completeVaultDeposit(holder, fiatDeposit, depositOperationId);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(vaultBalance);
+  const deposit = await program.account.fiatDeposit.fetch(fiatDeposit);
+  expect(deposit.processedBy.toBase58()).to.equal(admin.publicKey.toBase58());
+});
+
+it("Retry a deposit completion without crediting it twice", async () => {
+  const depositor = await onboardUser("Retried Depositor");
+  const fiatDeposit = await initiateVaultDeposit(depositor, 1_000_000);
+
+  await expectAnchorError(
+    completeVaultDeposit(depositor, fiatDeposit, new Array(16).fill(0)),
+    "InvalidOperationId"
+  );
+  const operationId = randomOperationId();
+  await completeVaultDeposit(depositor, fiatDeposit, operationId);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(990_000);
+  const completed = await program.account.fiatDeposit.fetch(fiatDeposit);
+  expect(completed.processedBy.toBase58()).to.equal(admin.publicKey.toBase58());
+  expect(completed.operationId).to.deep.equal(operationId);
+
+  // A processor retrying the same operation succeeds without a second credit. Waiting for the
+  // next block keeps the retry from being dropped as a duplicate transaction
+  await waitForChainTime(await chainTime());
+  await completeVaultDeposit(depositor, fiatDeposit, operationId);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(990_000);
+
+  // A different operation completing it again is a real double completion
+  await expectAnchorError(
+    completeVaultDeposit(depositor, fiatDeposit, randomOperationId()),
+    "InvalidDepositStatus"
+  );
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(990_000);
+});
+
+it("Sweep shard and fee vault dust back into the treasury", async () => {
+  const sweepDust = (authority: Keypair, threshold: number, tokenAccounts: PublicKey[]) =>
+    program.methods
+      .sweepDust(new anchor.BN(threshold), tokenAccounts.length)
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+        treasury,
+        feeVault,
+        mint,
+        treasurySigner,
+        treasuryTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        tokenAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+      )
+      .signers([authority])
+      .rpc();
+
+  const shard = treasuryShardAddress(0);
+  await mintTo(provider.connection, admin, mint, shard, admin, 700);
+  const feeDust = await tokenBalance(feeVaultTokenAccount);
+  const threshold = Math.max(feeDust, 700) + 1;
+
+  await expectAnchorError(sweepDust(user, threshold, [shard, feeVaultTokenAccount]), "Unauthorized");
+  // Only the treasury's shards and its fee vault can be swept
+  const outsider = await createAccount(provider.connection, admin, mint, admin.publicKey, Keypair.generate());
+  await expectAnchorError(sweepDust(admin, threshold, [outsider]), "InvalidBatchAccounts");
+  await expectAnchorError(sweepDust(admin, threshold, []), "InvalidBatchAccounts");
+
+  const reserves = await tokenBalance(treasuryTokenAccount);
+  const { totalClaimed } = await program.account.feeVault.fetch(feeVault);
+  await sweepDust(admin, threshold, [shard, feeVaultTokenAccount]);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reserves + 700 + feeDust);
+  expect(await tokenBalance(shard)).to.equal(0);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(0);
+  // Fee dust moved into the treasury is booked as claimed
+  expect((await program.account.feeVault.fetch(feeVault)).totalClaimed.toNumber()).to.equal(
+    totalClaimed.toNumber() + feeDust
+  );
+
+  // Balances at or above the threshold are working funds, not dust
+  await mintTo(provider.connection, admin, mint, shard, admin, 5_000);
+  await sweepDust(admin, 1_000, [shard]);
+  expect(await tokenBalance(shard)).to.equal(5_000);
+  await rebalanceTreasuryShards(shard, treasuryTokenAccount, 5_000);
+});
+
+const standingWithdrawalAddress = (vaultUser: VaultUser) =>
+  findPda(Buffer.from("standing_withdrawal"), vaultUser.keypair.publicKey.toBuffer(), mint.toBuffer());
+
+const createStandingWithdrawal = (
+  vaultUser: VaultUser,
+  amount: number,
+  interval: number,
+  firstExecutionTs: number,
+  maxOccurrences = 0,
+  coSigner: Keypair = null
+) =>
+  program.methods
+    .createStandingWithdrawal(
+      new anchor.BN(amount),
+      new anchor.BN(interval),
+      new anchor.BN(firstExecutionTs),
+      maxOccurrences
+    )
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      coSigner: coSigner?.publicKey ?? null,
+      vault: vaultUser.vault,
+      tokenWhitelist,
+      mint,
+      payoutDestination: vaultUser.payoutDestination,
+      standingWithdrawal: standingWithdrawalAddress(vaultUser),
+      systemProgram: SystemProgram.programId,
+    })
+    .signers(coSigner ? [vaultUser.keypair, coSigner] : [vaultUser.keypair])
+    .rpc();
+
+// Mirrors `StandingWithdrawal::next_reference_id`, which names the withdrawal each run opens
+const standingReferenceId = async (standingWithdrawal: PublicKey) => {
+  const order = await program.account.standingWithdrawal.fetch(standingWithdrawal);
+  const userHex = Buffer.from(order.user.toBytes().subarray(0, 6)).toString("hex");
+  const createdAtHex = ("00000000" + (order.createdAt.toNumber() >>> 0).toString(16)).slice(-8);
+  return `SW${userHex}-${createdAtHex}-${order.executedCount}`;
+};
+
+// Runs a due standing withdrawal as `caller` and returns the withdrawal it opened
+const executeStandingWithdrawal = async (vaultUser: VaultUser, caller: Keypair) => {
+  const standingWithdrawal = standingWithdrawalAddress(vaultUser);
+  const referenceId = await standingReferenceId(standingWithdrawal);
+  const fiatWithdrawal = findPda(
+    Buffer.from("fiat_withdrawal"),
+    vaultUser.keypair.publicKey.toBuffer(),
+    mint.toBuffer(),
+    Buffer.from(referenceId)
+  );
+  await program.methods
+    .executeStandingWithdrawal()
+    .accounts({
+      caller: caller.publicKey,
+      protocolConfig,
+      standingWithdrawal,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      vault: vaultUser.vault,
+      tokenWhitelist,
+      mint,
+      priceFeed: null,
+      payoutDestination: vaultUser.payoutDestination,
+      userLimits: vaultUser.userLimits,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      treasury,
+      withdrawalQueue: findPda(Buffer.from("withdrawal_queue")),
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      fiatWithdrawal,
+      referenceRegistry: findPda(
+        Buffer.from("reference_registry"),
+        createHash("sha256").update(referenceId).digest()
+      ),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([caller])
+    .rpc();
+  return fiatWithdrawal;
+};
+
+const cancelStandingWithdrawal = (vaultUser: VaultUser) =>
+  program.methods
+    .cancelStandingWithdrawal()
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      vault: vaultUser.vault,
+      standingWithdrawal: standingWithdrawalAddress(vaultUser),
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+
+it("Standing withdrawals run on schedule through a permissionless crank", async () => {
+  const DAY = 24 * 60 * 60;
+  const holder = await onboardUser("Salary Holder");
+  await fundVault(holder, 1_000_000);
+
+  await expectAnchorError(
+    createStandingWithdrawal(holder, 100_000, 60, (await chainTime()) + 5),
+    "InvalidStandingWithdrawalInterval"
+  );
+  // A start in the past would let the first run fire the moment the order exists
+  await expectAnchorError(
+    createStandingWithdrawal(holder, 100_000, DAY, (await chainTime()) - 60),
+    "InvalidStandingWithdrawalStart"
+  );
+
+  const firstExecutionTs = (await chainTime()) + 5;
+  await createStandingWithdrawal(holder, 100_000, DAY, firstExecutionTs, 12);
+  const order = await program.account.standingWithdrawal.fetch(standingWithdrawalAddress(holder));
+  expect(order.amount.toNumber()).to.equal(100_000);
+  expect(order.nextExecutionTs.toNumber()).to.equal(firstExecutionTs);
+  expect(order.maxOccurrences).to.equal(12);
+
+  // Anyone may crank the order, but only once it is due
+  const caller = await fundedKeypair();
+  await expectAnchorError(executeStandingWithdrawal(holder, caller), "StandingWithdrawalNotDue");
+  await waitForChainTime(firstExecutionTs);
+
+  const fiatWithdrawal = await executeStandingWithdrawal(holder, caller);
+  const opened = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(Object.keys(opened.status)[0]).to.equal("pending");
+  expect(opened.amount.toNumber()).to.equal(100_000);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(900_000);
+
+  const executed = await program.account.standingWithdrawal.fetch(standingWithdrawalAddress(holder));
+  expect(executed.executedCount).to.equal(1);
+  // The crank ran after the scheduled time, so the next run is a full interval from when it ran
+  expect(executed.nextExecutionTs.toNumber()).to.be.greaterThan(firstExecutionTs + DAY);
+  await expectAnchorError(executeStandingWithdrawal(holder, caller), "StandingWithdrawalNotDue");
+
+  // Cancelling stops future runs; the withdrawal already opened settles as usual
+  await cancelStandingWithdrawal(holder);
+  const closed = await program.account.standingWithdrawal.fetchNullable(standingWithdrawalAddress(holder));
+  expect(closed).to.be.null;
+  await completeFiatWithdrawal(holder, fiatWithdrawal);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("completed");
+});
+
+const vaultDelegateAddress = (vaultUser: VaultUser, delegate: PublicKey) =>
+  findPda(Buffer.from("vault_delegate"), vaultUser.vault.toBuffer(), delegate.toBuffer());
+
+const delegateVaultAuthority = async (
+  vaultUser: VaultUser,
+  delegate: PublicKey,
+  perTxLimit: number,
+  expiry: number
+) => {
+  const vaultDelegate = vaultDelegateAddress(vaultUser, delegate);
+  await program.methods
+    .delegateVaultAuthority(delegate, new anchor.BN(perTxLimit), new anchor.BN(expiry))
+    .accounts({
+      owner: vaultUser.keypair.publicKey,
+      vault: vaultUser.vault,
+      vaultDelegate,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+  return vaultDelegate;
+};
+
+// Opens a withdrawal out of `vaultUser`'s vault signed by `delegate` instead of the owner
+const initiateDelegatedWithdrawal = async (
+  vaultUser: VaultUser,
+  delegate: Keypair,
+  amount: number,
+  coSigner: Keypair = null
+) => {
+  const { referenceId, fiatWithdrawal, referenceRegistry } = nextWithdrawalReference(vaultUser);
+  await program.methods
+    .initiateDelegatedWithdrawal(new anchor.BN(amount), referenceId)
+    .accounts({
+      delegate: delegate.publicKey,
+      vaultDelegate: vaultDelegateAddress(vaultUser, delegate.publicKey),
+      userBlacklist: vaultUser.userBlacklist,
+      protocolConfig,
+      userProfile: vaultUser.userProfile,
+      coSigner: coSigner?.publicKey ?? null,
+      vault: vaultUser.vault,
+      tokenWhitelist,
+      mint,
+      priceFeed: null,
+      payoutDestination: vaultUser.payoutDestination,
+      userLimits: vaultUser.userLimits,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      treasury,
+      withdrawalQueue: findPda(Buffer.from("withdrawal_queue")),
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      fiatWithdrawal,
+      referenceRegistry,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers(coSigner ? [delegate, coSigner] : [delegate])
+    .rpc();
+  return fiatWithdrawal;
+};
+
+it("Vault delegates open withdrawals within their per-tx limit", async () => {
+  const owner = await onboardUser("Withdrawal Delegator");
+  await fundVault(owner, 3_000_000);
+  const delegate = await fundedKeypair();
+  const vaultDelegate = await delegateVaultAuthority(
+    owner,
+    delegate.publicKey,
+    1_000_000,
+    (await chainTime()) + 3600
+  );
+
+  // The withdrawal is the owner's, paid to the owner's destination, though the delegate signed it
+  const fiatWithdrawal = await initiateDelegatedWithdrawal(owner, delegate, 800_000);
+  const opened = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(opened.user.toBase58()).to.equal(owner.keypair.publicKey.toBase58());
+  expect(opened.amount.toNumber()).to.equal(800_000);
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(2_200_000);
+
+  await expectAnchorError(
+    initiateDelegatedWithdrawal(owner, delegate, 1_500_000),
+    "DelegateLimitExceeded"
+  );
+
+  await program.methods
+    .revokeVaultDelegate()
+    .accounts({
+      owner: owner.keypair.publicKey,
+      vault: owner.vault,
+      vaultDelegate,
+    })
+    .signers([owner.keypair])
+    .rpc();
+
+  // Once revoked the delegate has no grant to sign with
+  await expectAnchorError(
+    initiateDelegatedWithdrawal(owner, delegate, 100_000),
+    "AccountNotInitialized"
+  );
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(2_200_000);
+
+  await completeFiatWithdrawal(owner, fiatWithdrawal);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("completed");
+});
+
+const closeVault = (vaultUser: VaultUser, tokenAccounts: PublicKey[]) =>
+  program.methods
+    .closeVault()
+    .accounts({
+      owner: vaultUser.keypair.publicKey,
+      vault: vaultUser.vault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      protocolStats,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .remainingAccounts(
+      tokenAccounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+    )
+    .signers([vaultUser.keypair])
+    .rpc();
+
+it("Close an empty vault and reclaim its rent", async () => {
+  const holder = await onboardUser("Leaving Holder");
+  await fundVault(holder, 1);
+  await expectAnchorError(closeVault(holder, [holder.vaultTokenAccount]), "VaultNotEmpty");
+
+  const leaver = await onboardUser("Empty Leaver");
+  // Every token account the vault opened has to be passed in and closed along with it
+  await expectAnchorError(closeVault(leaver, []), "VaultTokenAccountsOpen");
+
+  // Outstanding grants such as a vault delegate keep the vault open until they are revoked
+  const delegate = Keypair.generate();
+  const vaultDelegate = await delegateVaultAuthority(
+    leaver,
+    delegate.publicKey,
+    1_000_000,
+    (await chainTime()) + 3600
+  );
+  await expectAnchorError(closeVault(leaver, [leaver.vaultTokenAccount]), "VaultHasOpenPositions");
+  await program.methods
+    .revokeVaultDelegate()
+    .accounts({
+      owner: leaver.keypair.publicKey,
+      vault: leaver.vault,
+      vaultDelegate,
+    })
+    .signers([leaver.keypair])
+    .rpc();
+
+  const lamportsBefore = await provider.connection.getBalance(leaver.keypair.publicKey);
+  await closeVault(leaver, [leaver.vaultTokenAccount]);
+  expect(await provider.connection.getAccountInfo(leaver.vault)).to.be.null;
+  expect(await provider.connection.getAccountInfo(leaver.vaultTokenAccount)).to.be.null;
+  // Rent from both accounts comes back to the owner, well above the transaction fee
+  expect(await provider.connection.getBalance(leaver.keypair.publicKey)).to.be.greaterThan(
+    lamportsBefore
+  );
+});
+
+it("Shared vault transfers execute once the approval threshold is met", async () => {
+  const creator = await onboardUser("Shared Creator");
+  const coOwner = await onboardUser("Shared Co-Owner");
+  const sharedVault = findPda(Buffer.from("shared_vault"), creator.keypair.publicKey.toBuffer());
+
+  await program.methods
+    .createSharedVault([creator.keypair.publicKey, coOwner.keypair.publicKey], 2)
+    .accounts({
+      creator: creator.keypair.publicKey,
+      userBlacklist: creator.userBlacklist,
+      userProfile: creator.userProfile,
+      sharedVault,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([creator.keypair])
+    .rpc();
+
+  const sharedTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    sharedVault,
+    Keypair.generate()
+  );
+  await mintTo(provider.connection, admin, mint, sharedTokenAccount, admin, 3_000_000);
+
+  const pendingVaultAction = findPda(
+    Buffer.from("pending_vault_action"),
+    sharedVault.toBuffer(),
+    new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .proposeSharedVaultTransfer(new anchor.BN(1_000_000))
+    .accounts({
+      proposer: creator.keypair.publicKey,
+      proposerBlacklist: creator.userBlacklist,
+      protocolConfig,
+      proposerProfile: creator.userProfile,
+      sharedVault,
+      vaultTokenAccount: sharedTokenAccount,
+      tokenWhitelist,
+      destinationVault: coOwner.vault,
+      destinationOwnerBlacklist: coOwner.userBlacklist,
+      destinationTokenAccount: coOwner.vaultTokenAccount,
+      pendingVaultAction,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([creator.keypair])
+    .rpc();
+
+  const execute = () =>
+    program.methods
+      .executeSharedVaultAction()
+      .accounts({
+        executor: creator.keypair.publicKey,
+        protocolConfig,
+        sharedVault,
+        pendingVaultAction,
+        proposer: creator.keypair.publicKey,
+        proposerProfile: creator.userProfile,
+        proposerLimits: creator.userLimits,
+        vaultTokenAccount: sharedTokenAccount,
+        destinationTokenAccount: coOwner.vaultTokenAccount,
+        destinationVault: coOwner.vault,
+        destinationOwnerBlacklist: coOwner.userBlacklist,
+        mint,
+        tokenWhitelist,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      // Every co-owner's blacklist marker, so a blacklisted co-owner blocks the transfer
+      .remainingAccounts(
+        [creator, coOwner].map((owner) => ({
+          pubkey: owner.userBlacklist,
+          isSigner: false,
+          isWritable: false,
+        }))
+      )
+      .signers([creator.keypair])
+      .rpc();
+
+  // Only the proposer's approval so far
+  await expectAnchorError(execute(), "VaultActionInsufficientApprovals");
+
+  const approve = (owner: VaultUser) =>
+    program.methods
+      .approveSharedVaultAction()
+      .accounts({
+        owner: owner.keypair.publicKey,
+        ownerBlacklist: owner.userBlacklist,
+        ownerProfile: owner.userProfile,
+        sharedVault,
+        pendingVaultAction,
+      })
+      .signers([owner.keypair])
+      .rpc();
+
+  await expectAnchorError(approve(creator), "VaultActionAlreadyApproved");
+  await approve(coOwner);
+  await execute();
+
+  expect(await tokenBalance(sharedTokenAccount)).to.equal(2_000_000);
+  expect(await tokenBalance(coOwner.vaultTokenAccount)).to.equal(1_000_000);
+  expect(await provider.connection.getAccountInfo(pendingVaultAction)).to.be.null;
+});
+
+const sessionAddress = (vaultUser: VaultUser, sessionKey: PublicKey) =>
+  findPda(Buffer.from("session"), vaultUser.keypair.publicKey.toBuffer(), sessionKey.toBuffer());
+
+const createSession = async (
+  vaultUser: VaultUser,
+  sessionKey: PublicKey,
+  expiry: number,
+  maxAmount: number
+) => {
+  const session = sessionAddress(vaultUser, sessionKey);
+  await program.methods
+    .createSession(sessionKey, new anchor.BN(expiry), new anchor.BN(maxAmount))
+    .accounts({
+      owner: vaultUser.keypair.publicKey,
+      vault: vaultUser.vault,
+      session,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+  return session;
+};
+
+// Opens a withdrawal out of `vaultUser`'s vault signed by one of their session keys
+const initiateSessionWithdrawal = async (
+  vaultUser: VaultUser,
+  sessionKey: Keypair,
+  amount: number,
+  coSigner: Keypair = null
+) => {
+  const { referenceId, fiatWithdrawal, referenceRegistry } = nextWithdrawalReference(vaultUser);
+  await program.methods
+    .initiateSessionWithdrawal(new anchor.BN(amount), referenceId)
+    .accounts({
+      sessionKey: sessionKey.publicKey,
+      session: sessionAddress(vaultUser, sessionKey.publicKey),
+      userBlacklist: vaultUser.userBlacklist,
+      protocolConfig,
+      userProfile: vaultUser.userProfile,
+      coSigner: coSigner?.publicKey ?? null,
+      vault: vaultUser.vault,
+      tokenWhitelist,
+      mint,
+      priceFeed: null,
+      payoutDestination: vaultUser.payoutDestination,
+      userLimits: vaultUser.userLimits,
+      vaultTokenAccount: vaultUser.vaultTokenAccount,
+      treasury,
+      withdrawalQueue: findPda(Buffer.from("withdrawal_queue")),
+      escrowAuthority,
+      withdrawalEscrow: withdrawalEscrow(),
+      fiatWithdrawal,
+      referenceRegistry,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      userTxIndex: vaultUser.userTxIndex,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers(coSigner ? [sessionKey, coSigner] : [sessionKey])
+    .rpc();
+  return fiatWithdrawal;
+};
+
+it("Session keys open withdrawals within their budget until they expire", async () => {
+  const owner = await onboardUser("Session Withdrawer");
+  await fundVault(owner, 3_000_000);
+  const sessionKey = await fundedKeypair();
+
+  // Sessions are short-lived by design
+  await expectAnchorError(
+    createSession(owner, sessionKey.publicKey, (await chainTime()) + 8 * 24 * 60 * 60, 1_000_000),
+    "InvalidSessionExpiry"
+  );
+  const session = await createSession(owner, sessionKey.publicKey, (await chainTime()) + 3600, 1_000_000);
+
+  const fiatWithdrawal = await initiateSessionWithdrawal(owner, sessionKey, 600_000);
+  const opened = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(opened.user.toBase58()).to.equal(owner.keypair.publicKey.toBase58());
+  expect(opened.amount.toNumber()).to.equal(600_000);
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(2_400_000);
+  expect((await program.account.session.fetch(session)).spent.toNumber()).to.equal(600_000);
+
+  // The budget covers the session's lifetime, not each withdrawal
+  await expectAnchorError(
+    initiateSessionWithdrawal(owner, sessionKey, 600_000),
+    "SessionLimitExceeded"
+  );
+
+  const shortKey = await fundedKeypair();
+  const shortExpiry = (await chainTime()) + 2;
+  await createSession(owner, shortKey.publicKey, shortExpiry, 1_000_000);
+  await waitForChainTime(shortExpiry);
+  await expectAnchorError(initiateSessionWithdrawal(owner, shortKey, 100_000), "SessionExpired");
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(2_400_000);
+
+  await completeFiatWithdrawal(owner, fiatWithdrawal);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("completed");
+});
+
+it("A burst of withdrawals trips the outflow breaker until the admin clears it", async () => {
+  const setTreasuryOutflowLimit = (authority: Keypair, maxHourlyOutflowBps: number) =>
+    program.methods
+      .setTreasuryOutflowLimit(maxHourlyOutflowBps)
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+        treasury,
+      })
+      .signers([authority])
+      .rpc();
+  const clearOutflowThrottle = (authority: Keypair) =>
+    program.methods
+      .clearOutflowThrottle()
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+        treasury,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([authority])
+      .rpc();
+
+  await expectAnchorError(setTreasuryOutflowLimit(user, 1), "Unauthorized");
+  await expectAnchorError(setTreasuryOutflowLimit(admin, 10001), "InvalidOutflowLimit");
+  await expectAnchorError(clearOutflowThrottle(admin), "OutflowNotThrottled");
+
+  const holder = await onboardUser("Outflow Holder");
+  await fundVault(holder, 1_000_000);
+
+  // At 1 bps of the outstanding balance per hour, a single withdrawal is enough to trip it
+  await setTreasuryOutflowLimit(admin, 1);
+  const tripped = await initiateFiatWithdrawal(holder, 300_000);
+  expect((await program.account.treasury.fetch(treasury)).throttled).to.equal(true);
+  await expectAnchorError(initiateFiatWithdrawal(holder, 300_000), "OutflowThrottled");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(700_000);
+
+  // Turning the limit off does not reopen withdrawals on its own
+  await setTreasuryOutflowLimit(admin, 0);
+  await expectAnchorError(initiateFiatWithdrawal(holder, 300_000), "OutflowThrottled");
+  await expectAnchorError(clearOutflowThrottle(user), "Unauthorized");
+  await clearOutflowThrottle(admin);
+  expect((await program.account.treasury.fetch(treasury)).throttled).to.equal(false);
+
+  const resumed = await initiateFiatWithdrawal(holder, 300_000);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(400_000);
+  await completeFiatWithdrawal(holder, tripped);
+  await completeFiatWithdrawal(holder, resumed);
+  expect(await withdrawalStatus(resumed)).to.equal("completed");
+});
+
+const MOCK_SWAP_ADAPTER_ID = new PublicKey("2kfCxJAco7GodkhzeAHLDeY4viQdaSSF3vU2s8aDV8Ha");
+
+// Whitelists a fresh 6-decimal mint with its own treasury
+const whitelistMint = async (symbol: string, name: string, isStable: boolean) => {
+  const tokenMint = await createMint(
+    provider.connection,
+    admin,
+    admin.publicKey,
+    null,
+    6,
+    undefined,
+    { commitment: 'confirmed' }
+  );
+  const proposedToken = findPda(Buffer.from("proposed_token"), tokenMint.toBuffer());
+  const whitelist = findPda(Buffer.from("token_whitelist"), tokenMint.toBuffer());
+
+  await program.methods
+    .proposeToken(symbol, name, isStable)
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      mint: tokenMint,
+      proposedToken,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  await program.methods
+    .approveToken()
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      scheduledChange: null,
+      proposedToken,
+      proposer: admin.publicKey,
+      mint: tokenMint,
+      tokenWhitelist: whitelist,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const mintTreasury = findPda(Buffer.from("treasury"), tokenMint.toBuffer());
+  const mintTreasuryTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    tokenMint,
+    treasurySigner,
+    Keypair.generate()
+  );
+  await program.methods
+    .initializeTreasury()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      mint: tokenMint,
+      treasurySigner,
+      treasuryTokenAccount: mintTreasuryTokenAccount,
+      treasury: mintTreasury,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  return {
+    mint: tokenMint,
+    tokenWhitelist: whitelist,
+    treasury: mintTreasury,
+    treasuryTokenAccount: mintTreasuryTokenAccount,
+  };
+};
+
+it("Swap between whitelisted mints inside a vault", async () => {
+  const euro = await whitelistMint("EURC", "Euro Coin", true);
+  const [swapConfig] = PublicKey.findProgramAddressSync(
+    [Buffer.from("swap_config")],
+    program.programId
+  );
+  await program.methods
+    .setSwapConfig(MOCK_SWAP_ADAPTER_ID, 25)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      swapConfig,
+    })
+    .signers([admin])
+    .rpc();
+
+  // The mock adapter swaps 1:1 against a pool of output tokens it owns
+  const [poolAuthority] = PublicKey.findProgramAddressSync(
+    [Buffer.from("pool")],
+    MOCK_SWAP_ADAPTER_ID
+  );
+  const poolInputTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    poolAuthority,
+    Keypair.generate()
+  );
+  const poolOutputTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    euro.mint,
+    poolAuthority,
+    Keypair.generate()
+  );
+  await mintTo(provider.connection, admin, euro.mint, poolOutputTokenAccount, admin, 10_000_000);
+
+  const trader = await onboardUser("Trader");
+  const euroVaultTokenAccount = await createVaultTokenAccount(trader, euro.mint);
+  const credited = await depositToVault(trader, 2_000_000);
+
+  const swap = (amountIn: number, minAmountOut: number) =>
+    program.methods
+      .swapInVault(new anchor.BN(amountIn), new anchor.BN(minAmountOut))
+      .accounts({
+        user: trader.keypair.publicKey,
+        userBlacklist: trader.userBlacklist,
+        protocolConfig,
+        userProfile: trader.userProfile,
+        vault: trader.vault,
+        swapConfig,
+        inputMint: mint,
+        outputMint: euro.mint,
+        inputTokenWhitelist: tokenWhitelist,
+        outputTokenWhitelist: euro.tokenWhitelist,
+        vaultInputTokenAccount: trader.vaultTokenAccount,
+        vaultOutputTokenAccount: euroVaultTokenAccount,
+        inputTreasury: treasury,
+        outputTreasury: euro.treasury,
+        feeVault,
+        feeVaultTokenAccount,
+        protocolStats,
+        swapProgram: MOCK_SWAP_ADAPTER_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .remainingAccounts([
+        { pubkey: poolInputTokenAccount, isSigner: false, isWritable: true },
+        { pubkey: poolOutputTokenAccount, isSigner: false, isWritable: true },
+        { pubkey: poolAuthority, isSigner: false, isWritable: false },
+      ])
+      .signers([trader.keypair])
+      .rpc();
+
+  const feesBefore = await tokenBalance(feeVaultTokenAccount);
+  const usdOutstandingBefore = await outstandingBalance(treasury);
+  const euroOutstandingBefore = await outstandingBalance(euro.treasury);
+
+  // 25 bps of the input is the swap fee; the rest is swapped 1:1
+  await swap(1_000_000, 997_500);
+  expect(await tokenBalance(trader.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  expect(await tokenBalance(euroVaultTokenAccount)).to.equal(997_500);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(feesBefore + 2_500);
+  expect(await tokenBalance(poolInputTokenAccount)).to.equal(997_500);
+  expect(await outstandingBalance(treasury)).to.equal(usdOutstandingBefore - 1_000_000);
+  expect(await outstandingBalance(euro.treasury)).to.equal(euroOutstandingBefore + 997_500);
+
+  await expectAnchorError(swap(500_000, 500_000), "SlippageExceeded");
+  expect(await tokenBalance(trader.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(feesBefore + 2_500);
+  expect(await outstandingBalance(treasury)).to.equal(usdOutstandingBefore - 1_000_000);
+});
+
+it("Deposit to and withdraw from savings", async () => {
+  const saver = await onboardUser("Saver");
+  const credited = await depositToVault(saver, 2_000_000);
+  const savingsPosition = findPda(
+    Buffer.from("savings_position"),
+    saver.keypair.publicKey.toBuffer(),
+    mint.toBuffer()
+  );
+
+  const reservesBefore = await tokenBalance(treasuryTokenAccount);
+  const outstandingBefore = await outstandingBalance(treasury);
+
+  await program.methods
+    .depositToSavings(new anchor.BN(1_000_000))
+    .accounts({
+      user: saver.keypair.publicKey,
+      userBlacklist: saver.userBlacklist,
+      protocolConfig,
+      userProfile: saver.userProfile,
+      vault: saver.vault,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: saver.vaultTokenAccount,
+      treasury,
+      treasuryTokenAccount,
+      savingsPosition,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([saver.keypair])
+    .rpc();
+
+  // Savings stay the user's claim on the treasury, so nothing is released
+  expect(await tokenBalance(saver.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reservesBefore + 1_000_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore);
+  let position = await program.account.savingsPosition.fetch(savingsPosition);
+  expect(position.principal.toNumber()).to.equal(1_000_000);
+
+  const withdraw = (amount: number) =>
+    program.methods
+      .withdrawFromSavings(new anchor.BN(amount))
+      .accounts({
+        user: saver.keypair.publicKey,
+        protocolConfig,
+        userProfile: saver.userProfile,
+        vault: saver.vault,
+        savingsPosition,
+        mint,
+        vaultTokenAccount: saver.vaultTokenAccount,
+        treasury,
+        treasurySigner,
+        treasuryTokenAccount,
+        protocolStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([saver.keypair])
+      .rpc();
+
+  await expectAnchorError(withdraw(2_000_000), "InsufficientFunds");
+
+  // Principal is paid out before interest, so nothing new becomes outstanding
+  await withdraw(600_000);
+  expect(await tokenBalance(saver.vaultTokenAccount)).to.equal(credited - 400_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reservesBefore + 400_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore);
+  position = await program.account.savingsPosition.fetch(savingsPosition);
+  expect(position.principal.toNumber()).to.equal(400_000);
+});
+
+it("Open and break a term deposit", async () => {
+  const [termDepositConfig] = PublicKey.findProgramAddressSync(
+    [Buffer.from("term_deposit_config")],
+    program.programId
+  );
+  const insuranceFund = findPda(Buffer.from("insurance_fund"), mint.toBuffer());
+  const insuranceFundTokenAccount = findPda(
+    Buffer.from("insurance_fund_token_account"),
+    mint.toBuffer()
+  );
+  await program.methods
+    .initializeInsuranceFund()
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      mint,
+      insuranceFund,
+      insuranceFundTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const depositor = await onboardUser("Term Depositor");
+  const credited = await depositToVault(depositor, 2_000_000);
+  const termDeposit = findPda(
+    Buffer.from("term_deposit"),
+    depositor.keypair.publicKey.toBuffer(),
+    new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+  );
+
+  const open = (amount: number, termDays: number) =>
+    program.methods
+      .openTermDeposit(new anchor.BN(amount), termDays)
+      .accounts({
+        user: depositor.keypair.publicKey,
+        userBlacklist: depositor.userBlacklist,
+        protocolConfig,
+        userProfile: depositor.userProfile,
+        vault: depositor.vault,
+        termDepositConfig,
+        tokenWhitelist,
+        mint,
+        vaultTokenAccount: depositor.vaultTokenAccount,
+        treasury,
+        treasuryTokenAccount,
+        termDeposit,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([depositor.keypair])
+      .rpc();
+
+  await expectAnchorError(open(1_000_000, 60), "TermNotOffered");
+
+  const reservesBefore = await tokenBalance(treasuryTokenAccount);
+  const outstandingBefore = await outstandingBalance(treasury);
+  await open(1_000_000, 30);
+
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reservesBefore + 1_000_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore);
+  const deposit = await program.account.termDeposit.fetch(termDeposit);
+  expect(deposit.amount.toNumber()).to.equal(1_000_000);
+  expect(deposit.rateBps).to.equal(300);
+  expect(deposit.earlyWithdrawalPenaltyBps).to.equal(100);
+
+  await expectAnchorError(
+    program.methods
+      .redeemTermDeposit()
+      .accounts({
+        user: depositor.keypair.publicKey,
+        protocolConfig,
+        userProfile: depositor.userProfile,
+        vault: depositor.vault,
+        termDeposit,
+        mint,
+        vaultTokenAccount: depositor.vaultTokenAccount,
+        treasury,
+        treasurySigner,
+        treasuryTokenAccount,
+        protocolStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([depositor.keypair])
+      .rpc(),
+    "TermDepositNotMatured"
+  );
+
+  // Breaking early forfeits the interest and 1% of the principal to the insurance fund
+  await program.methods
+    .breakTermDeposit()
+    .accounts({
+      user: depositor.keypair.publicKey,
+      protocolConfig,
+      userProfile: depositor.userProfile,
+      vault: depositor.vault,
+      termDeposit,
+      mint,
+      vaultTokenAccount: depositor.vaultTokenAccount,
+      treasury,
+      treasurySigner,
+      treasuryTokenAccount,
+      insuranceFund,
+      insuranceFundTokenAccount,
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([depositor.keypair])
+    .rpc();
+
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(credited - 10_000);
+  expect(await tokenBalance(insuranceFundTokenAccount)).to.equal(10_000);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reservesBefore);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore - 10_000);
+  expect(await provider.connection.getAccountInfo(termDeposit)).to.be.null;
+});
+
+it("Stake, earn fee rewards, claim and unstake", async () => {
+  const [stakingPool] = PublicKey.findProgramAddressSync(
+    [Buffer.from("staking_pool")],
+    program.programId
+  );
+  const [stakeVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("stake_vault")],
+    program.programId
+  );
+  const [rewardVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from("staking_reward_vault")],
+    program.programId
+  );
+  const { stakingMint } = await program.account.stakingPool.fetch(stakingPool);
+
+  const staker = await fundedKeypair();
+  const stakerTokenAccount = await createAccount(provider.connection, staker, stakingMint, staker.publicKey);
+  const stakerRewardAccount = await createAccount(provider.connection, staker, mint, staker.publicKey);
+  await mintTo(provider.connection, admin, stakingMint, stakerTokenAccount, admin, 5_000_000);
+  const stakePosition = findPda(Buffer.from("stake_position"), staker.publicKey.toBuffer());
+
+  const fundRewards = (amount: number) =>
+    program.methods
+      .fundStakingRewards(new anchor.BN(amount))
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        stakingPool,
+        rewardMint: mint,
+        rewardVault,
+        feeVault,
+        feeVaultTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+  // Rewards can only be shared once someone has staked
+  await expectAnchorError(fundRewards(10_000), "NothingStaked");
+
+  await program.methods
+    .stake(new anchor.BN(4_000_000))
+    .accounts({
+      user: staker.publicKey,
+      userBlacklist: findPda(Buffer.from("blacklist"), staker.publicKey.toBuffer()),
+      protocolConfig,
+      stakingPool,
+      stakingMint,
+      userTokenAccount: stakerTokenAccount,
+      stakeVault,
+      stakePosition,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([staker])
+    .rpc();
+  expect(await tokenBalance(stakerTokenAccount)).to.equal(1_000_000);
+  expect(await tokenBalance(stakeVault)).to.equal(4_000_000);
+
+  const feesBefore = await tokenBalance(feeVaultTokenAccount);
+  await fundRewards(10_000);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(feesBefore - 10_000);
+  expect(await tokenBalance(rewardVault)).to.equal(10_000);
+
+  const claim = () =>
+    program.methods
+      .claimStakingRewards()
+      .accounts({
+        user: staker.publicKey,
+        protocolConfig,
+        stakingPool,
+        rewardMint: mint,
+        rewardVault,
+        userRewardTokenAccount: stakerRewardAccount,
+        stakePosition,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([staker])
+      .rpc();
+
+  // Rewards are fee revenue paid to the wallet, not a user balance the treasury backs
+  const outstandingBefore = await outstandingBalance(treasury);
+  await claim();
+  expect(await tokenBalance(stakerRewardAccount)).to.equal(10_000);
+  expect(await tokenBalance(rewardVault)).to.equal(0);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore);
+  await expectAnchorError(claim(), "InvalidAmount");
+
+  const unstake = (amount: number) =>
+    program.methods
+      .unstake(new anchor.BN(amount))
+      .accounts({
+        user: staker.publicKey,
+        protocolConfig,
+        stakingPool,
+        stakingMint,
+        userTokenAccount: stakerTokenAccount,
+        stakeVault,
+        stakePosition,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([staker])
+      .rpc();
+
+  await expectAnchorError(unstake(5_000_000), "InsufficientFunds");
+  await unstake(4_000_000);
+  expect(await tokenBalance(stakerTokenAccount)).to.equal(5_000_000);
+  expect(await tokenBalance(stakeVault)).to.equal(0);
+});
+
+it("Draw, repay and liquidate a credit line", async () => {
+  const borrower = await onboardUser("Borrower");
+  const credited = await depositToVault(borrower, 2_000_000);
+  const creditLine = findPda(
+    Buffer.from("credit_line"),
+    borrower.keypair.publicKey.toBuffer(),
+    mint.toBuffer()
+  );
+
+  const setCreditParams = (maxLtvBps: number, liquidationLtvBps: number, whitelist = tokenWhitelist) =>
+    program.methods
+      .setTokenCreditParams(maxLtvBps, liquidationLtvBps, 500, 500)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        tokenWhitelist: whitelist,
+      })
+      .signers([admin])
+      .rpc();
+  const setPegCurrency = (whitelist: PublicKey, pegCurrency: string) =>
+    program.methods
+      .setTokenPegCurrency([...Buffer.from(pegCurrency)])
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        tokenWhitelist: whitelist,
+      })
+      .signers([admin])
+      .rpc();
+  const openCreditLine = (collateral: {
+    mint: PublicKey;
+    tokenWhitelist: PublicKey;
+    treasury: PublicKey;
+    treasuryTokenAccount: PublicKey;
+    vaultTokenAccount: PublicKey;
+  }) =>
+    program.methods
+      .openCreditLine(new anchor.BN(1_000_000))
+      .accounts({
+        user: borrower.keypair.publicKey,
+        userBlacklist: borrower.userBlacklist,
+        protocolConfig,
+        userProfile: borrower.userProfile,
+        vault: borrower.vault,
+        collateralWhitelist: collateral.tokenWhitelist,
+        debtWhitelist: tokenWhitelist,
+        collateralMint: collateral.mint,
+        vaultTokenAccount: collateral.vaultTokenAccount,
+        treasury: collateral.treasury,
+        treasuryTokenAccount: collateral.treasuryTokenAccount,
+        creditLine: findPda(
+          Buffer.from("credit_line"),
+          borrower.keypair.publicKey.toBuffer(),
+          collateral.mint.toBuffer()
+        ),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([borrower.keypair])
+      .rpc();
+  const usdCollateral = {
+    mint,
+    tokenWhitelist,
+    treasury,
+    treasuryTokenAccount,
+    vaultTokenAccount: borrower.vaultTokenAccount,
+  };
+
+  // Collateral is valued at par against the debt, so both need the same recorded peg
+  await expectAnchorError(openCreditLine(usdCollateral), "PegCurrencyMismatch");
+  await expectAnchorError(setPegCurrency(tokenWhitelist, "usd"), "InvalidCurrencyCode");
+  await setPegCurrency(tokenWhitelist, "USD");
+
+  const euro = await whitelistMint("EURC", "Euro Coin", true);
+  await setCreditParams(8000, 9000, euro.tokenWhitelist);
+  await setPegCurrency(euro.tokenWhitelist, "EUR");
+  await expectAnchorError(
+    openCreditLine({ ...euro, vaultTokenAccount: await createVaultTokenAccount(borrower, euro.mint) }),
+    "PegCurrencyMismatch"
+  );
+
+  const outstandingBefore = await outstandingBalance(treasury);
+
+  // USDC collateral backing USDC debt, so LTV is simply debt over collateral
+  await openCreditLine(usdCollateral);
+  expect(await tokenBalance(borrower.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  // Collateral is still owed back to the borrower
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore);
+
+  const draw = (amount: number) =>
+    program.methods
+      .draw(new anchor.BN(amount))
+      .accounts({
+        user: borrower.keypair.publicKey,
+        userBlacklist: borrower.userBlacklist,
+        protocolConfig,
+        userProfile: borrower.userProfile,
+        vault: borrower.vault,
+        creditLine,
+        collateralWhitelist: tokenWhitelist,
+        debtWhitelist: tokenWhitelist,
+        debtMint: mint,
+        vaultTokenAccount: borrower.vaultTokenAccount,
+        treasury,
+        treasurySigner,
+        treasuryTokenAccount,
+        protocolStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([borrower.keypair])
+      .rpc();
+
+  const liquidate = () =>
+    program.methods
+      .liquidateCreditLine()
+      .accounts({
+        liquidator: admin.publicKey,
+        owner: borrower.keypair.publicKey,
+        creditLine,
+        vault: borrower.vault,
+        collateralWhitelist: tokenWhitelist,
+        collateralMint: mint,
+        debtMint: mint,
+        vaultTokenAccount: borrower.vaultTokenAccount,
+        treasury,
+        treasurySigner,
+        treasuryTokenAccount,
+        protocolStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([admin])
+      .rpc();
+
+  // Up to the 80% max LTV
+  await draw(800_000);
+  expect(await tokenBalance(borrower.vaultTokenAccount)).to.equal(credited - 200_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore + 800_000);
+
+  await expectAnchorError(draw(10_000), "CreditLimitExceeded");
+  await expectAnchorError(liquidate(), "CreditLineHealthy");
+
+  await program.methods
+    .repay(new anchor.BN(300_000))
+    .accounts({
+      user: borrower.keypair.publicKey,
+      vault: borrower.vault,
+      creditLine,
+      debtMint: mint,
+      vaultTokenAccount: borrower.vaultTokenAccount,
+      treasury,
+      treasuryTokenAccount,
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([borrower.keypair])
+    .rpc();
+  expect(await tokenBalance(borrower.vaultTokenAccount)).to.equal(credited - 500_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore + 500_000);
+  const line = await program.account.creditLine.fetch(creditLine);
+  expect(line.principal.toNumber()).to.equal(500_000);
+
+  // Lowering the liquidation LTV below the line's 50% makes it liquidatable: the treasury keeps
+  // the 500_000 debt plus the 5% penalty and returns the rest of the collateral
+  await setCreditParams(4000, 4500);
+  try {
+    await liquidate();
+  } finally {
+    await setCreditParams(8000, 9000);
+  }
+  expect(await tokenBalance(borrower.vaultTokenAccount)).to.equal(credited - 500_000 + 475_000);
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore + 500_000 - 525_000);
+  expect(await provider.connection.getAccountInfo(creditLine)).to.be.null;
+});
+
+it("Deposit vault tokens into a yield strategy and withdraw them with earnings", async () => {
+  const yieldStrategy = findPda(
+    Buffer.from("yield_strategy"),
+    mint.toBuffer(),
+    MOCK_YIELD_ADAPTER_ID.toBuffer()
+  );
+  const registerStrategy = (authority: Keypair) =>
+    program.methods
+      .registerYieldStrategy("Mock Lending", { low: {} })
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+        tokenWhitelist,
+        mint,
+        adapterProgram: MOCK_YIELD_ADAPTER_ID,
+        yieldStrategy,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([authority])
+      .rpc();
+  const updateStrategy = (isActive: boolean) =>
+    program.methods
+      .updateYieldStrategy(isActive, null)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        yieldStrategy,
+      })
+      .signers([admin])
+      .rpc();
+
+  await expectAnchorError(registerStrategy(user), "Unauthorized");
+  await registerStrategy(admin);
+
+  const saver = await onboardUser("Strategy Saver");
+  const credited = await depositToVault(saver, 2_000_000);
+  const strategyPosition = findPda(
+    Buffer.from("strategy_position"),
+    saver.keypair.publicKey.toBuffer(),
+    yieldStrategy.toBuffer()
+  );
+  // The mock adapter keeps deposits in a pool token account it owns
+  const poolTokenAccount = await createAccount(
+    provider.connection,
+    admin,
+    mint,
+    yieldPoolAuthority,
+    Keypair.generate()
+  );
+  const positionAccounts = {
+    user: saver.keypair.publicKey,
+    userBlacklist: saver.userBlacklist,
+    protocolConfig,
+    userProfile: saver.userProfile,
+    vault: saver.vault,
+    yieldStrategy,
+    tokenWhitelist,
+    mint,
+    vaultTokenAccount: saver.vaultTokenAccount,
+    strategyPosition,
+    adapterProgram: MOCK_YIELD_ADAPTER_ID,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    systemProgram: SystemProgram.programId,
+    eventCounter,
+    eventAuthority,
+    program: program.programId,
+  };
+  const poolAccounts = [
+    { pubkey: poolTokenAccount, isSigner: false, isWritable: true },
+    { pubkey: yieldPoolAuthority, isSigner: false, isWritable: false },
+  ];
+  const deposit = (amount: number) =>
+    program.methods
+      .depositToStrategy(new anchor.BN(amount))
+      .accounts({
+        ...positionAccounts,
+      })
+      .remainingAccounts(poolAccounts)
+      .signers([saver.keypair])
+      .rpc();
+  const withdraw = (amount: number) =>
+    program.methods
+      .withdrawFromStrategy(new anchor.BN(amount))
+      .accounts({
+        ...positionAccounts,
+      })
+      .remainingAccounts(poolAccounts)
+      .signers([saver.keypair])
+      .rpc();
+
+  await deposit(1_000_000);
+  expect(await tokenBalance(saver.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  expect(await tokenBalance(poolTokenAccount)).to.equal(1_000_000);
+  let position = await program.account.strategyPosition.fetch(strategyPosition);
+  expect(position.deposited.toNumber()).to.equal(1_000_000);
+
+  // A paused strategy takes no new deposits but still pays out
+  await updateStrategy(false);
+  await expectAnchorError(deposit(100_000), "StrategyNotActive");
+
+  // Yield accrued in the pool comes back above principal and is recorded as earned
+  await mintTo(provider.connection, admin, mint, poolTokenAccount, admin, 50_000);
+  await withdraw(1_050_000);
+  expect(await tokenBalance(saver.vaultTokenAccount)).to.equal(credited + 50_000);
+  position = await program.account.strategyPosition.fetch(strategyPosition);
+  expect(position.deposited.toNumber()).to.equal(0);
+  expect(position.earned.toNumber()).to.equal(50_000);
+  const strategy = await program.account.yieldStrategy.fetch(yieldStrategy);
+  expect(strategy.totalDeposited.toNumber()).to.equal(0);
+  expect(strategy.totalEarned.toNumber()).to.equal(50_000);
+});
+
+it("Convert vault tokens between currencies at a quoted rate", async () => {
+  const euro = await whitelistMint("EURT", "Euro Token", true);
+  await mintTo(provider.connection, admin, euro.mint, euro.treasuryTokenAccount, admin, 10_000_000);
+  const fxPair = findPda(Buffer.from("fx_pair"), mint.toBuffer(), euro.mint.toBuffer());
+
+  await program.methods
+    .setFxPair({ quoted: {} }, 20, new anchor.BN(300), true)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      fromMint: mint,
+      toMint: euro.mint,
+      fxPair,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+  await program.methods
+    .quoteFxRate(new anchor.BN(900_000_000)) // 1 USD = 0.90 EUR
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      fxPair,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  const converter = await onboardUser("Converter");
+  const credited = await depositToVault(converter, 2_000_000);
+  const euroVaultTokenAccount = await createVaultTokenAccount(converter, euro.mint);
+  const conversion = findPda(
+    Buffer.from("conversion"),
+    converter.keypair.publicKey.toBuffer(),
+    new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+  );
+
+  const convert = (amount: number, minAmountOut: number) =>
+    program.methods
+      .convertCurrency(new anchor.BN(amount), new anchor.BN(minAmountOut))
+      .accounts({
+        user: converter.keypair.publicKey,
+        userBlacklist: converter.userBlacklist,
+        protocolConfig,
+        userProfile: converter.userProfile,
+        vault: converter.vault,
+        fxPair,
+        fromMint: mint,
+        toMint: euro.mint,
+        fromTokenWhitelist: tokenWhitelist,
+        toTokenWhitelist: euro.tokenWhitelist,
+        fromPriceFeed: null,
+        toPriceFeed: null,
+        vaultFromTokenAccount: converter.vaultTokenAccount,
+        vaultToTokenAccount: euroVaultTokenAccount,
+        fromTreasury: treasury,
+        fromTreasuryTokenAccount: treasuryTokenAccount,
+        toTreasury: euro.treasury,
+        toTreasuryTokenAccount: euro.treasuryTokenAccount,
+        treasurySigner,
+        conversion,
+        protocolStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([converter.keypair])
+      .rpc();
+
+  // 1 USD buys 900_000 EUR units before the 20 bps spread
+  await expectAnchorError(convert(1_000_000, 898_201), "SlippageExceeded");
+
+  const usdReservesBefore = await tokenBalance(treasuryTokenAccount);
+  const usdOutstandingBefore = await outstandingBalance(treasury);
+  const euroOutstandingBefore = await outstandingBalance(euro.treasury);
+  await convert(1_000_000, 898_200);
+
+  expect(await tokenBalance(converter.vaultTokenAccount)).to.equal(credited - 1_000_000);
+  expect(await tokenBalance(euroVaultTokenAccount)).to.equal(898_200);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(usdReservesBefore + 1_000_000);
+  expect(await tokenBalance(euro.treasuryTokenAccount)).to.equal(10_000_000 - 898_200);
+  expect(await outstandingBalance(treasury)).to.equal(usdOutstandingBefore - 1_000_000);
+  expect(await outstandingBalance(euro.treasury)).to.equal(euroOutstandingBefore + 898_200);
+
+  const receipt = await program.account.conversion.fetch(conversion);
+  expect(receipt.amountOut.toNumber()).to.equal(898_200);
+  expect(receipt.spreadAmount.toNumber()).to.equal(1_800);
+});
+
+const createPaymentRequest = async (issuer: VaultUser, payee: PublicKey, amount: number) => {
+  const profile = await program.account.userProfile.fetch(issuer.userProfile);
+  const invoice = findPda(
+    Buffer.from("invoice"),
+    issuer.keypair.publicKey.toBuffer(),
+    profile.invoiceCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .createPaymentRequest(payee, new anchor.BN(amount), "Order 42", new anchor.BN((await chainTime()) + 3600))
+    .accounts({
+      issuer: issuer.keypair.publicKey,
+      issuerBlacklist: issuer.userBlacklist,
+      protocolConfig,
+      userProfile: issuer.userProfile,
+      tokenWhitelist,
+      mint,
+      invoice,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([issuer.keypair])
+    .rpc();
+  return invoice;
+};
+
+const payInvoice = (
+  payer: VaultUser,
+  invoice: PublicKey,
+  payee: VaultUser,
+  allowlistEntry: PublicKey | null = null
+) =>
+  program.methods
+    .payInvoice()
+    .accounts({
+      user: payer.keypair.publicKey,
+      userBlacklist: payer.userBlacklist,
+      protocolConfig,
+      userProfile: payer.userProfile,
+      session: null,
+      vaultDelegate: null,
+      vault: payer.vault,
+      invoice,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: payer.vaultTokenAccount,
+      payeeVault: payee.vault,
+      allowlistEntry,
+      payeeVaultTokenAccount: payee.vaultTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([payer.keypair])
+    .rpc();
+
+it("Pay and cancel payment requests", async () => {
+  const issuer = await onboardUser("Invoice Issuer");
+  const payer = await onboardUser("Invoice Payer");
+  await fundVault(payer, 5_000_000);
+
+  const invoice = await createPaymentRequest(issuer, issuer.keypair.publicKey, 1_250_000);
+  await payInvoice(payer, invoice, issuer);
+
+  expect(await tokenBalance(payer.vaultTokenAccount)).to.equal(3_750_000);
+  expect(await tokenBalance(issuer.vaultTokenAccount)).to.equal(1_250_000);
+  const paid = await program.account.invoice.fetch(invoice);
+  expect(Object.keys(paid.status)[0]).to.equal("paid");
+
+  // An invoice is paid at most once
+  await expectAnchorError(payInvoice(payer, invoice, issuer), "InvoiceNotOpen");
+
+  const cancelled = await createPaymentRequest(issuer, issuer.keypair.publicKey, 500_000);
+  await program.methods
+    .cancelPaymentRequest()
+    .accounts({
+      issuer: issuer.keypair.publicKey,
+      invoice: cancelled,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([issuer.keypair])
+    .rpc();
+  await expectAnchorError(payInvoice(payer, cancelled, issuer), "InvoiceNotOpen");
+  expect(await tokenBalance(payer.vaultTokenAccount)).to.equal(3_750_000);
+});
+
+interface Merchant {
+  owner: VaultUser;
+  merchantProfile: PublicKey;
+  merchantTokenAccount: PublicKey;
+}
+
+// Registers `owner` as a merchant with a settlement account for the test mint. Merchants start
+// inactive until the admin sets their terms
+const registerMerchant = async (owner: VaultUser, name: string): Promise<Merchant> => {
+  const merchantProfile = findPda(Buffer.from("merchant"), owner.keypair.publicKey.toBuffer());
+  await program.methods
+    .registerMerchant(name)
+    .accounts({
+      owner: owner.keypair.publicKey,
+      ownerBlacklist: owner.userBlacklist,
+      userProfile: owner.userProfile,
+      vault: owner.vault,
+      merchantProfile,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([owner.keypair])
+    .rpc();
+
+  const merchantTokenAccount = getAssociatedTokenAddressSync(mint, merchantProfile, true);
+  await program.methods
+    .createMerchantTokenAccount()
+    .accounts({
+      payer: owner.keypair.publicKey,
+      merchantProfile,
+      mint,
+      tokenWhitelist,
+      merchantTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([owner.keypair])
+    .rpc();
+  return { owner, merchantProfile, merchantTokenAccount };
+};
+
+const setMerchantTerms = (merchant: Merchant, feeBps: number, isActive: boolean) =>
+  program.methods
+    .setMerchantTerms(feeBps, isActive)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+      merchantProfile: merchant.merchantProfile,
+    })
+    .signers([admin])
+    .rpc();
+
+// Pays `merchant` from `customer`'s vault and returns the payment receipt
+const payMerchant = async (
+  customer: VaultUser,
+  merchant: Merchant,
+  amount: number,
+  reference: PublicKey | null = null,
+  memo = "Order 7"
+) => {
+  const profile = await program.account.userProfile.fetch(customer.userProfile);
+  const paymentReceipt = findPda(
+    Buffer.from("payment_receipt"),
+    customer.keypair.publicKey.toBuffer(),
+    profile.paymentCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .payMerchant(new anchor.BN(amount), memo)
+    .accounts({
+      user: customer.keypair.publicKey,
+      userBlacklist: customer.userBlacklist,
+      protocolConfig,
+      userProfile: customer.userProfile,
+      session: null,
+      vaultDelegate: null,
+      vault: customer.vault,
+      merchantProfile: merchant.merchantProfile,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: customer.vaultTokenAccount,
+      merchantTokenAccount: merchant.merchantTokenAccount,
+      treasury,
+      feeVault,
+      feeVaultTokenAccount,
+      protocolStats,
+      reference,
+      paymentReceipt,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([customer.keypair])
+    .rpc();
+  return paymentReceipt;
+};
+
+it("Pay an active merchant net of its fee", async () => {
+  const merchant = await registerMerchant(await onboardUser("Merchant Owner"), "Corner Shop");
+  const customer = await onboardUser("Merchant Customer");
+  await depositToVault(customer, 3_000_000);
+  const customerBefore = await tokenBalance(customer.vaultTokenAccount);
+
+  // Merchants take no payments until the admin onboards them
+  await expectAnchorError(payMerchant(customer, merchant, 1_000_000), "MerchantNotActive");
+
+  await setMerchantTerms(merchant, 200, true);
+  const feesBefore = await tokenBalance(feeVaultTokenAccount);
+  const outstandingBefore = await outstandingBalance(treasury);
+  const paymentReceipt = await payMerchant(customer, merchant, 1_000_000);
+
+  expect(await tokenBalance(customer.vaultTokenAccount)).to.equal(customerBefore - 1_000_000);
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(980_000);
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(feesBefore + 20_000);
+  // Only the fee leaves user balances; the merchant's share is still owed to it
+  expect(await outstandingBalance(treasury)).to.equal(outstandingBefore - 20_000);
+
+  const profile = await program.account.merchantProfile.fetch(merchant.merchantProfile);
+  expect(profile.totalReceived.toNumber()).to.equal(980_000);
+  expect(profile.totalFees.toNumber()).to.equal(20_000);
+  expect(profile.paymentCount.toNumber()).to.equal(1);
+  const receipt = await program.account.paymentReceipt.fetch(paymentReceipt);
+  expect(receipt.amount.toNumber()).to.equal(1_000_000);
+  expect(receipt.feeAmount.toNumber()).to.equal(20_000);
+
+  // A suspended merchant stops taking payments
+  await setMerchantTerms(merchant, 200, false);
+  await expectAnchorError(payMerchant(customer, merchant, 1_000_000), "MerchantNotActive");
+  expect(await tokenBalance(customer.vaultTokenAccount)).to.equal(customerBefore - 1_000_000);
+});
+
+it("Settle a merchant's balance through a fiat withdrawal", async () => {
+  const merchant = await registerMerchant(await onboardUser("Settling Owner"), "Night Market");
+  const customer = await onboardUser("Settlement Customer");
+  await fundVault(customer, 2_000_000);
+  await setMerchantTerms(merchant, 100, true);
+  await payMerchant(customer, merchant, 1_000_000);
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(990_000);
+
+  const settle = async (amount: number) => {
+    const { referenceId, fiatWithdrawal, referenceRegistry } = nextWithdrawalReference(merchant.owner);
+    await program.methods
+      .settleMerchant(new anchor.BN(amount), referenceId)
+      .accounts({
+        owner: merchant.owner.keypair.publicKey,
+        ownerBlacklist: merchant.owner.userBlacklist,
+        protocolConfig,
+        userProfile: merchant.owner.userProfile,
+        coSigner: null,
+        vault: merchant.owner.vault,
+        merchantProfile: merchant.merchantProfile,
+        tokenWhitelist,
+        mint,
+        priceFeed: null,
+        payoutDestination: merchant.owner.payoutDestination,
+        userLimits: merchant.owner.userLimits,
+        merchantTokenAccount: merchant.merchantTokenAccount,
+        treasury,
+        withdrawalQueue: findPda(Buffer.from("withdrawal_queue")),
+        escrowAuthority,
+        withdrawalEscrow: withdrawalEscrow(),
+        fiatWithdrawal,
+        referenceRegistry,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        userTxIndex: merchant.owner.userTxIndex,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([merchant.owner.keypair])
+      .rpc();
+    return fiatWithdrawal;
+  };
+
+  const escrowBefore = await tokenBalance(withdrawalEscrow());
+  await expectAnchorError(settle(1_000_000), "InsufficientFunds");
+  const settled = 600_000;
+  const fiatWithdrawal = await settle(settled);
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(990_000 - settled);
+  expect(await tokenBalance(withdrawalEscrow())).to.equal(escrowBefore + settled);
+  const opened = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(opened.user.toBase58()).to.equal(merchant.owner.keypair.publicKey.toBase58());
+  expect(opened.amount.toNumber()).to.equal(settled);
+  const profile = await program.account.merchantProfile.fetch(merchant.merchantProfile);
+  expect(profile.totalSettled.toNumber()).to.equal(settled);
+  expect(profile.settlementCount.toNumber()).to.equal(1);
+
+  // The payout settles like any other withdrawal, net of the withdrawal fee
+  const feesBefore = await tokenBalance(feeVaultTokenAccount);
+  const reservesBefore = await tokenBalance(treasuryTokenAccount);
+  await completeFiatWithdrawal(merchant.owner, fiatWithdrawal);
+  const fee = Math.floor((settled * WITHDRAWAL_FEE_BPS) / 10000);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("completed");
+  expect(await tokenBalance(feeVaultTokenAccount)).to.equal(feesBefore + fee);
+  expect(await tokenBalance(treasuryTokenAccount)).to.equal(reservesBefore + settled - fee);
+  expect(await tokenBalance(withdrawalEscrow())).to.equal(escrowBefore);
+});
+
+const chargeSubscription = (subscription: PublicKey, subscriber: VaultUser, merchant: Merchant) =>
+  program.methods
+    .chargeSubscription()
+    .accounts({
+      caller: admin.publicKey,
+      protocolConfig,
+      subscription,
+      userBlacklist: subscriber.userBlacklist,
+      userProfile: subscriber.userProfile,
+      vault: subscriber.vault,
+      merchantProfile: merchant.merchantProfile,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: subscriber.vaultTokenAccount,
+      merchantTokenAccount: merchant.merchantTokenAccount,
+      treasury,
+      feeVault,
+      feeVaultTokenAccount,
+      protocolStats,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+it("Charge a subscription once per interval until it is revoked", async () => {
+  const merchant = await registerMerchant(await onboardUser("Streaming Service"), "Streamflix");
+  await setMerchantTerms(merchant, 100, true);
+  const subscriber = await onboardUser("Subscriber");
+  await depositToVault(subscriber, 3_000_000);
+  const subscriberBefore = await tokenBalance(subscriber.vaultTokenAccount);
+
+  const subscription = findPda(
+    Buffer.from("subscription"),
+    subscriber.keypair.publicKey.toBuffer(),
+    merchant.merchantProfile.toBuffer()
+  );
+  const authorizeSubscription = (interval: number) =>
+    program.methods
+      .authorizeSubscription(new anchor.BN(500_000), new anchor.BN(interval))
+      .accounts({
+        subscriber: subscriber.keypair.publicKey,
+        subscriberBlacklist: subscriber.userBlacklist,
+        userProfile: subscriber.userProfile,
+        merchantProfile: merchant.merchantProfile,
+        tokenWhitelist,
+        mint,
+        subscription,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([subscriber.keypair])
+      .rpc();
+
+  // Intervals shorter than a day would let a merchant drain the vault
+  await expectAnchorError(authorizeSubscription(3600), "InvalidSubscriptionInterval");
+  await authorizeSubscription(24 * 60 * 60);
+
+  // The first charge is due as soon as the subscription is authorized
+  await chargeSubscription(subscription, subscriber, merchant);
+  expect(await tokenBalance(subscriber.vaultTokenAccount)).to.equal(subscriberBefore - 500_000);
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(495_000);
+  const charged = await program.account.subscription.fetch(subscription);
+  expect(charged.chargeCount.toNumber()).to.equal(1);
+  expect(charged.totalCharged.toNumber()).to.equal(500_000);
+
+  await expectAnchorError(chargeSubscription(subscription, subscriber, merchant), "SubscriptionNotDue");
+
+  await program.methods
+    .revokeSubscription()
+    .accounts({
+      subscriber: subscriber.keypair.publicKey,
+      subscription,
+    })
+    .signers([subscriber.keypair])
+    .rpc();
+  expect(await provider.connection.getAccountInfo(subscription)).to.be.null;
+  expect(await tokenBalance(subscriber.vaultTokenAccount)).to.equal(subscriberBefore - 500_000);
+});
+
+const claimHandle = async (vaultUser: VaultUser, handle: string) => {
+  const handleAccount = findPda(Buffer.from("handle"), Buffer.from(handle));
+  await program.methods
+    .claimHandle(handle)
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      userBlacklist: vaultUser.userBlacklist,
+      userProfile: vaultUser.userProfile,
+      vault: vaultUser.vault,
+      handleAccount,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([vaultUser.keypair])
+    .rpc();
+  return handleAccount;
+};
+
+// Sends `amount` from `sender`'s vault to the owner of `handle`, signed by `signer` (the
+// owner, one of their session keys or one of their vault delegates)
+const sendToUsername = async (
+  sender: VaultUser,
+  recipient: VaultUser,
+  handle: string,
+  amount: number,
+  signer: Keypair = sender.keypair,
+  signerAccounts: { session?: PublicKey; vaultDelegate?: PublicKey; allowlistEntry?: PublicKey } = {}
+) => {
+  const profile = await program.account.userProfile.fetch(sender.userProfile);
+  const paymentReceipt = findPda(
+    Buffer.from("payment_receipt"),
+    sender.keypair.publicKey.toBuffer(),
+    profile.paymentCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .sendToUsername(handle, new anchor.BN(amount), "")
+    .accounts({
+      user: signer.publicKey,
+      userBlacklist: sender.userBlacklist,
+      protocolConfig,
+      userProfile: sender.userProfile,
+      session: signerAccounts.session ?? null,
+      vaultDelegate: signerAccounts.vaultDelegate ?? null,
+      vault: sender.vault,
+      handleAccount: findPda(Buffer.from("handle"), Buffer.from(handle)),
+      recipientBlacklist: recipient.userBlacklist,
+      recipientVault: recipient.vault,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: sender.vaultTokenAccount,
+      recipientVaultTokenAccount: recipient.vaultTokenAccount,
+      allowlistEntry: signerAccounts.allowlistEntry ?? null,
+      reference: null,
+      paymentReceipt,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([signer])
+    .rpc();
+};
+
+it("Send to a username from a vault", async () => {
+  const sender = await onboardUser("Username Sender");
+  const recipient = await onboardUser("Username Recipient");
+  const bystander = await onboardUser("Username Bystander");
+  await fundVault(sender, 2_000_000);
+  await claimHandle(recipient, "recipient_89");
+
+  await sendToUsername(sender, recipient, "recipient_89", 750_000);
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(1_250_000);
+  expect(await tokenBalance(recipient.vaultTokenAccount)).to.equal(750_000);
+  const profile = await program.account.userProfile.fetch(sender.userProfile);
+  expect(profile.paymentCount.toNumber()).to.equal(1);
+
+  // Funds only go to the vault of the name's owner
+  await expectAnchorError(
+    sendToUsername(sender, bystander, "recipient_89", 100_000),
+    "ConstraintSeeds"
+  );
+  await expectAnchorError(
+    sendToUsername(sender, recipient, "recipient_89", 5_000_000),
+    "InsufficientFunds"
+  );
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(1_250_000);
+  expect(await tokenBalance(bystander.vaultTokenAccount)).to.equal(0);
+});
+
+it("Claim, transfer and release a handle", async () => {
+  const first = await onboardUser("Handle Owner");
+  const second = await onboardUser("Handle Heir");
+
+  await expectAnchorError(claimHandle(first, "no spaces!"), "InvalidHandle");
+  await expectAnchorError(claimHandle(first, "ab"), "InvalidHandle");
+  const handleAccount = await claimHandle(first, "handle_90");
+  const claimed = await program.account.handle.fetch(handleAccount);
+  expect(claimed.owner.toBase58()).to.equal(first.keypair.publicKey.toBase58());
+
+  // A handle is unique, so a second claim on it fails to create the account
+  let duplicateClaimed = true;
+  await claimHandle(second, "handle_90").catch(() => {
+    duplicateClaimed = false;
+  });
+  expect(duplicateClaimed).to.be.false;
+
+  const transferHandle = (from: VaultUser, to: VaultUser) =>
+    program.methods
+      .transferHandle()
+      .accounts({
+        user: from.keypair.publicKey,
+        userProfile: from.userProfile,
+        handleAccount,
+        newOwnerProfile: to.userProfile,
+        newOwnerBlacklist: to.userBlacklist,
+        newOwnerVault: to.vault,
+      })
+      .signers([from.keypair])
+      .rpc();
+  await expectAnchorError(transferHandle(second, second), "Unauthorized");
+  await transferHandle(first, second);
+  const transferred = await program.account.handle.fetch(handleAccount);
+  expect(transferred.owner.toBase58()).to.equal(second.keypair.publicKey.toBase58());
+  expect((await program.account.userProfile.fetch(first.userProfile)).handleCount).to.equal(0);
+  expect((await program.account.userProfile.fetch(second.userProfile)).handleCount).to.equal(1);
+
+  const releaseHandle = (owner: VaultUser) =>
+    program.methods
+      .releaseHandle()
+      .accounts({
+        user: owner.keypair.publicKey,
+        userProfile: owner.userProfile,
+        handleAccount,
+      })
+      .signers([owner.keypair])
+      .rpc();
+  await expectAnchorError(releaseHandle(first), "Unauthorized");
+  await releaseHandle(second);
+  expect(await provider.connection.getAccountInfo(handleAccount)).to.be.null;
+  expect((await program.account.userProfile.fetch(second.userProfile)).handleCount).to.equal(0);
+});
+
+it("Session keys send to a handle within their budget until they expire", async () => {
+  const owner = await onboardUser("Session Sender");
+  const recipient = await onboardUser("Session Recipient");
+  await claimHandle(recipient, "session_recipient");
+  await fundVault(owner, 5_000_000);
+
+  const sessionKey = await fundedKeypair();
+  const session = await createSession(owner, sessionKey.publicKey, (await chainTime()) + 3600, 1_000_000);
+
+  await sendToUsername(owner, recipient, "session_recipient", 600_000, sessionKey, { session });
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(4_400_000);
+  expect(await tokenBalance(recipient.vaultTokenAccount)).to.equal(600_000);
+  const sessionData = await program.account.session.fetch(session);
+  expect(sessionData.spent.toNumber()).to.equal(600_000);
+
+  // The budget covers the session's lifetime, not each transfer
+  await expectAnchorError(
+    sendToUsername(owner, recipient, "session_recipient", 600_000, sessionKey, { session }),
+    "SessionLimitExceeded"
+  );
+
+  const shortKey = await fundedKeypair();
+  const shortExpiry = (await chainTime()) + 2;
+  const shortSession = await createSession(owner, shortKey.publicKey, shortExpiry, 1_000_000);
+  await waitForChainTime(shortExpiry);
+  await expectAnchorError(
+    sendToUsername(owner, recipient, "session_recipient", 100_000, shortKey, { session: shortSession }),
+    "SessionExpired"
+  );
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(4_400_000);
+});
+
+it("Vault delegates send to a handle within their per-tx limit", async () => {
+  const owner = await onboardUser("Delegating Sender");
+  const recipient = await onboardUser("Delegate Recipient");
+  await claimHandle(recipient, "delegate_recipient");
+  await fundVault(owner, 5_000_000);
+
+  const delegate = await fundedKeypair();
+  const vaultDelegate = await delegateVaultAuthority(
+    owner,
+    delegate.publicKey,
+    1_000_000,
+    (await chainTime()) + 3600
+  );
+
+  await sendToUsername(owner, recipient, "delegate_recipient", 750_000, delegate, { vaultDelegate });
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(4_250_000);
+  expect(await tokenBalance(recipient.vaultTokenAccount)).to.equal(750_000);
+
+  await expectAnchorError(
+    sendToUsername(owner, recipient, "delegate_recipient", 1_500_000, delegate, { vaultDelegate }),
+    "DelegateLimitExceeded"
+  );
+
+  await program.methods
+    .revokeVaultDelegate()
+    .accounts({
+      owner: owner.keypair.publicKey,
+      vault: owner.vault,
+      vaultDelegate,
+    })
+    .signers([owner.keypair])
+    .rpc();
+
+  // Without a delegate grant the signer has no authority over the vault
+  await expectAnchorError(
+    sendToUsername(owner, recipient, "delegate_recipient", 500_000, delegate),
+    "Unauthorized"
+  );
+  expect(await tokenBalance(owner.vaultTokenAccount)).to.equal(4_250_000);
+});
+
+const claimableEscrowAuthority = findPda(Buffer.from("claimable_escrow_authority"));
+
+// Escrows `amount` from `sender`'s vault for `recipient` and returns the claimable transfer
+const createClaimableTransfer = async (
+  sender: VaultUser,
+  recipient: object,
+  amount: number,
+  expiresAt: number
+) => {
+  const profile = await program.account.userProfile.fetch(sender.userProfile);
+  const claimableTransfer = findPda(
+    Buffer.from("claimable_transfer"),
+    sender.keypair.publicKey.toBuffer(),
+    profile.claimableTransferCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .createClaimableTransfer(recipient, new anchor.BN(amount), new anchor.BN(expiresAt))
+    .accounts({
+      user: sender.keypair.publicKey,
+      userBlacklist: sender.userBlacklist,
+      protocolConfig,
+      userProfile: sender.userProfile,
+      session: null,
+      vaultDelegate: null,
+      vault: sender.vault,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: sender.vaultTokenAccount,
+      allowlistEntry: null,
+      escrowAuthority: claimableEscrowAuthority,
+      claimableEscrow: findPda(Buffer.from("claimable_escrow"), mint.toBuffer()),
+      claimableTransfer,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([sender.keypair])
+    .rpc();
+  return claimableTransfer;
+};
+
+const claimTransfer = (
+  claimer: VaultUser,
+  sender: VaultUser,
+  claimableTransfer: PublicKey,
+  claimKey: Keypair | null = null
+) =>
+  program.methods
+    .claimTransfer()
+    .accounts({
+      user: claimer.keypair.publicKey,
+      claimKey: claimKey?.publicKey ?? null,
+      userBlacklist: claimer.userBlacklist,
+      protocolConfig,
+      userProfile: claimer.userProfile,
+      vault: claimer.vault,
+      sender: sender.keypair.publicKey,
+      claimableTransfer,
+      mint,
+      vaultTokenAccount: claimer.vaultTokenAccount,
+      escrowAuthority: claimableEscrowAuthority,
+      claimableEscrow: findPda(Buffer.from("claimable_escrow"), mint.toBuffer()),
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers(claimKey ? [claimer.keypair, claimKey] : [claimer.keypair])
+    .rpc();
+
+it("Claim a transfer with its claim key or refund it once expired", async () => {
+  const sender = await onboardUser("Claimable Sender");
+  const claimer = await onboardUser("Claimable Claimer");
+  await fundVault(sender, 3_000_000);
+  const claimableEscrow = findPda(Buffer.from("claimable_escrow"), mint.toBuffer());
+
+  const claimKey = Keypair.generate();
+  const linkRecipient = { claimKey: { 0: claimKey.publicKey } };
+  const now = await chainTime();
+  await expectAnchorError(
+    createClaimableTransfer(sender, linkRecipient, 1_000_000, now - 1),
+    "InvalidClaimExpiry"
+  );
+  const linkTransfer = await createClaimableTransfer(sender, linkRecipient, 1_000_000, now + 3600);
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(2_000_000);
+  const escrowBefore = await tokenBalance(claimableEscrow);
+
+  // Only whoever holds the claim key's secret can claim it
+  await expectAnchorError(claimTransfer(claimer, sender, linkTransfer), "NotClaimRecipient");
+  await claimTransfer(claimer, sender, linkTransfer, claimKey);
+  expect(await tokenBalance(claimer.vaultTokenAccount)).to.equal(1_000_000);
+  expect(await tokenBalance(claimableEscrow)).to.equal(escrowBefore - 1_000_000);
+  expect(await provider.connection.getAccountInfo(linkTransfer)).to.be.null;
+
+  // A transfer left unclaimed past its expiry goes back to the sender
+  const expiresAt = (await chainTime()) + 2;
+  const walletTransfer = await createClaimableTransfer(
+    sender,
+    { wallet: { 0: claimer.keypair.publicKey } },
+    500_000,
+    expiresAt
+  );
+  const refundClaimableTransfer = () =>
+    program.methods
+      .refundClaimableTransfer()
+      .accounts({
+        user: sender.keypair.publicKey,
+        vault: sender.vault,
+        claimableTransfer: walletTransfer,
+        mint,
+        vaultTokenAccount: sender.vaultTokenAccount,
+        escrowAuthority: claimableEscrowAuthority,
+        claimableEscrow,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([sender.keypair])
+      .rpc();
+  await expectAnchorError(refundClaimableTransfer(), "ClaimableTransferNotExpired");
+
+  await waitForChainTime(expiresAt);
+  await expectAnchorError(claimTransfer(claimer, sender, walletTransfer), "ClaimableTransferExpired");
+  await refundClaimableTransfer();
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(2_000_000);
+  expect(await tokenBalance(claimer.vaultTokenAccount)).to.equal(1_000_000);
+});
+
+const createSplitBill = async (creator: VaultUser, shares: [VaultUser, number][]) => {
+  const profile = await program.account.userProfile.fetch(creator.userProfile);
+  const splitBill = findPda(
+    Buffer.from("split_bill"),
+    creator.keypair.publicKey.toBuffer(),
+    profile.splitBillCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .createSplitBill(
+      shares.map(([participant, amount]) => ({
+        participant: participant.keypair.publicKey,
+        amount: new anchor.BN(amount),
+      }))
+    )
+    .accounts({
+      creator: creator.keypair.publicKey,
+      creatorBlacklist: creator.userBlacklist,
+      protocolConfig,
+      userProfile: creator.userProfile,
+      vault: creator.vault,
+      tokenWhitelist,
+      mint,
+      splitBill,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([creator.keypair])
+    .rpc();
+  return splitBill;
+};
+
+const paySplitShare = (participant: VaultUser, splitBill: PublicKey, creator: VaultUser) =>
+  program.methods
+    .paySplitShare()
+    .accounts({
+      user: participant.keypair.publicKey,
+      userBlacklist: participant.userBlacklist,
+      protocolConfig,
+      userProfile: participant.userProfile,
+      session: null,
+      vaultDelegate: null,
+      vault: participant.vault,
+      splitBill,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: participant.vaultTokenAccount,
+      creatorVault: creator.vault,
+      allowlistEntry: null,
+      creatorVaultTokenAccount: creator.vaultTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([participant.keypair])
+    .rpc();
+
+it("Split a bill and collect each participant's share", async () => {
+  const creator = await onboardUser("Bill Creator");
+  const first = await onboardUser("Bill First");
+  const second = await onboardUser("Bill Second");
+  const outsider = await onboardUser("Bill Outsider");
+  await fundVault(first, 2_000_000);
+  await fundVault(second, 2_000_000);
+  await fundVault(outsider, 2_000_000);
+
+  // The creator never owes themselves a share
+  await expectAnchorError(
+    createSplitBill(creator, [[creator, 500_000], [first, 500_000]]),
+    "InvalidSplitShares"
+  );
+  const splitBill = await createSplitBill(creator, [[first, 600_000], [second, 400_000]]);
+
+  await paySplitShare(first, splitBill, creator);
+  await expectAnchorError(paySplitShare(first, splitBill, creator), "SplitShareAlreadyPaid");
+  await expectAnchorError(paySplitShare(outsider, splitBill, creator), "NotSplitParticipant");
+  await paySplitShare(second, splitBill, creator);
+
+  expect(await tokenBalance(first.vaultTokenAccount)).to.equal(1_400_000);
+  expect(await tokenBalance(second.vaultTokenAccount)).to.equal(1_600_000);
+  expect(await tokenBalance(outsider.vaultTokenAccount)).to.equal(2_000_000);
+  expect(await tokenBalance(creator.vaultTokenAccount)).to.equal(1_000_000);
+  const settled = await program.account.splitBill.fetch(splitBill);
+  expect(Object.keys(settled.status)[0]).to.equal("finalized");
+  expect(settled.paidAmount.toNumber()).to.equal(1_000_000);
+
+  // Once someone has paid, the creator can only finalize the bill, waiving unpaid shares
+  const partial = await createSplitBill(creator, [[first, 300_000], [second, 300_000]]);
+  await paySplitShare(first, partial, creator);
+  const closeSplitBill = (finalize: boolean) =>
+    (finalize ? program.methods.finalizeSplitBill() : program.methods.cancelSplitBill())
+      .accounts({
+        creator: creator.keypair.publicKey,
+        splitBill: partial,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([creator.keypair])
+      .rpc();
+  await expectAnchorError(closeSplitBill(false), "SplitBillHasPayments");
+  await closeSplitBill(true);
+  await expectAnchorError(paySplitShare(second, partial, creator), "SplitBillNotOpen");
+  expect(await tokenBalance(second.vaultTokenAccount)).to.equal(1_600_000);
+  expect(await tokenBalance(creator.vaultTokenAccount)).to.equal(1_300_000);
+});
+
+const paymentEscrowAuthority = findPda(Buffer.from("escrow_authority"));
+
+const openEscrow = async (
+  depositor: VaultUser,
+  counterparty: VaultUser,
+  arbiter: PublicKey,
+  amount: number
+) => {
+  const profile = await program.account.userProfile.fetch(depositor.userProfile);
+  const escrow = findPda(
+    Buffer.from("escrow"),
+    depositor.keypair.publicKey.toBuffer(),
+    profile.escrowCount.toArrayLike(Buffer, "le", 8)
+  );
+  await program.methods
+    .openEscrow(counterparty.keypair.publicKey, arbiter, new anchor.BN(amount))
+    .accounts({
+      user: depositor.keypair.publicKey,
+      userBlacklist: depositor.userBlacklist,
+      protocolConfig,
+      userProfile: depositor.userProfile,
+      vault: depositor.vault,
+      counterpartyBlacklist: counterparty.userBlacklist,
+      counterpartyVault: counterparty.vault,
+      allowlistEntry: null,
+      tokenWhitelist,
+      mint,
+      vaultTokenAccount: depositor.vaultTokenAccount,
+      escrowAuthority: paymentEscrowAuthority,
+      escrowTokenAccount: findPda(Buffer.from("escrow_tokens"), mint.toBuffer()),
+      escrow,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([depositor.keypair])
+    .rpc();
+  return escrow;
+};
+
+// Accounts shared by the release and resolve instructions, which both settle into the parties' vaults
+const escrowSettlementAccounts = (escrow: PublicKey, depositor: VaultUser, counterparty: VaultUser) => ({
+  escrow,
+  mint,
+  depositorVault: depositor.vault,
+  depositorVaultTokenAccount: depositor.vaultTokenAccount,
+  counterpartyVault: counterparty.vault,
+  counterpartyVaultTokenAccount: counterparty.vaultTokenAccount,
+  escrowAuthority: paymentEscrowAuthority,
+  escrowTokenAccount: findPda(Buffer.from("escrow_tokens"), mint.toBuffer()),
+  tokenProgram: TOKEN_PROGRAM_ID,
+  eventCounter,
+  eventAuthority,
+  program: program.programId,
+});
+
+it("Settle escrows by mutual release or by the arbiter", async () => {
+  const depositor = await onboardUser("Escrow Depositor");
+  const counterparty = await onboardUser("Escrow Counterparty");
+  const arbiter = Keypair.generate();
+  await fundVault(depositor, 3_000_000);
+
+  await expectAnchorError(
+    openEscrow(depositor, counterparty, counterparty.keypair.publicKey, 1_000_000),
+    "InvalidEscrowParties"
+  );
+  const disputed = await openEscrow(depositor, counterparty, arbiter.publicKey, 1_000_000);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(2_000_000);
+
+  const resolveEscrow = (signer: Keypair, counterpartyBps: number) =>
+    program.methods
+      .resolveEscrow(counterpartyBps)
+      .accounts({
+        arbiter: signer.publicKey,
+        depositor: depositor.keypair.publicKey,
+        ...escrowSettlementAccounts(disputed, depositor, counterparty),
+      })
+      .signers([signer])
+      .rpc();
+  // Only the named arbiter can decide a dispute
+  await expectAnchorError(resolveEscrow(counterparty.keypair, 10000), "Unauthorized");
+  await resolveEscrow(arbiter, 7000);
+  expect(await tokenBalance(counterparty.vaultTokenAccount)).to.equal(700_000);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(2_300_000);
+  expect(await provider.connection.getAccountInfo(disputed)).to.be.null;
+
+  const agreed = await openEscrow(depositor, counterparty, arbiter.publicKey, 500_000);
+  const releaseEscrow = (counterpartyBps: number) =>
+    program.methods
+      .releaseEscrow(counterpartyBps)
+      .accounts({
+        depositor: depositor.keypair.publicKey,
+        counterparty: counterparty.keypair.publicKey,
+        ...escrowSettlementAccounts(agreed, depositor, counterparty),
+      })
+      .signers([depositor.keypair, counterparty.keypair])
+      .rpc();
+  await expectAnchorError(releaseEscrow(10001), "InvalidEscrowSplit");
+  await releaseEscrow(10000);
+  expect(await tokenBalance(counterparty.vaultTokenAccount)).to.equal(1_200_000);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(1_800_000);
+});
+
+it("Issue a remittance quote for a corridor", async () => {
+  const sender = await onboardUser("Remitter");
+  await fundVault(sender, 2_000_000);
+  const quoteAddress = (quoteId: number) =>
+    findPda(
+      Buffer.from("remittance_quote"),
+      sender.keypair.publicKey.toBuffer(),
+      new anchor.BN(quoteId).toArrayLike(Buffer, "le", 8)
+    );
+  const issueRemittanceQuote = (
+    authority: Keypair,
+    quoteId: number,
+    toCurrency: string,
+    rate: number,
+    expiresAt: number
+  ) =>
+    program.methods
+      .issueRemittanceQuote(
+        sender.keypair.publicKey,
+        new anchor.BN(quoteId),
+        [...Buffer.from("USD")],
+        [...Buffer.from(toCurrency)],
+        new anchor.BN(rate),
+        new anchor.BN(expiresAt)
+      )
+      .accounts({
+        authority: authority.publicKey,
+        protocolConfig,
+        operatorRole: null,
+        tokenWhitelist,
+        mint,
+        remittanceQuote: quoteAddress(quoteId),
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([authority])
+      .rpc();
+
+  const expiresAt = (await chainTime()) + 600;
+  const rate = 18_500_000_000; // 1 USD = 18.5 MXN
+  // Only the admin or an FX quoting operator can lock a rate
+  await expectAnchorError(
+    issueRemittanceQuote(await fundedKeypair(), 94, "MXN", rate, expiresAt),
+    "Unauthorized"
+  );
+  await expectAnchorError(issueRemittanceQuote(admin, 94, "mxn", rate, expiresAt), "InvalidCurrencyCode");
+  await expectAnchorError(issueRemittanceQuote(admin, 94, "MXN", 0, expiresAt), "InvalidFxRate");
+  await expectAnchorError(
+    issueRemittanceQuote(admin, 94, "MXN", rate, expiresAt - 1200),
+    "InvalidQuoteExpiry"
+  );
+  await issueRemittanceQuote(admin, 94, "MXN", rate, expiresAt);
+  const remittanceQuote = quoteAddress(94);
+  const quote = await program.account.remittanceQuote.fetch(remittanceQuote);
+  expect(quote.user.toBase58()).to.equal(sender.keypair.publicKey.toBase58());
+  expect(quote.mint.toBase58()).to.equal(mint.toBase58());
+  expect(Buffer.from(quote.toCurrency).toString()).to.equal("MXN");
+  expect(quote.rate.toNumber()).to.equal(rate);
+  expect(quote.expiresAt.toNumber()).to.equal(expiresAt);
+  expect(quote.quotedBy.toBase58()).to.equal(admin.publicKey.toBase58());
+  expect(quote.usedBy).to.be.null;
+
+  // Initiating a withdrawal with the quote locks its rate for the fiat payout
+  const fiatWithdrawal = await initiateFiatWithdrawal(sender, 1_000_000, { remittanceQuote });
+  const used = await program.account.remittanceQuote.fetch(remittanceQuote);
+  expect(used.usedBy.toBase58()).to.equal(fiatWithdrawal.toBase58());
+  const locked = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(locked.remittanceQuote.toBase58()).to.equal(remittanceQuote.toBase58());
+  expect(Buffer.from(locked.payoutCurrency).toString()).to.equal("MXN");
+  expect(locked.payoutRate.toNumber()).to.equal(rate);
+  await expectAnchorError(
+    initiateFiatWithdrawal(sender, 500_000, { remittanceQuote }),
+    "RemittanceQuoteUsed"
+  );
+
+  await completeFiatWithdrawal(sender, fiatWithdrawal);
+  const fee = Math.floor((1_000_000 * WITHDRAWAL_FEE_BPS) / 10000);
+  const completed = await program.account.fiatWithdrawal.fetch(fiatWithdrawal);
+  expect(completed.netAmount.toNumber()).to.equal(1_000_000 - fee);
+  expect(completed.payoutAmount.toNumber()).to.equal(((1_000_000 - fee) * 185) / 10);
+
+  // A quote cannot be locked once it has expired
+  const shortExpiry = (await chainTime()) + 2;
+  await issueRemittanceQuote(admin, 95, "MXN", rate, shortExpiry);
+  await waitForChainTime(shortExpiry);
+  await expectAnchorError(
+    initiateFiatWithdrawal(sender, 500_000, { remittanceQuote: quoteAddress(95) }),
+    "RemittanceQuoteExpired"
+  );
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(1_000_000);
+});
+
+it("Find a merchant payment by its Solana Pay reference", async () => {
+  const merchant = await registerMerchant(await onboardUser("Point Of Sale"), "Coffee Cart");
+  await setMerchantTerms(merchant, 0, true);
+  const customer = await onboardUser("Point Of Sale Customer");
+  await fundVault(customer, 1_000_000);
+
+  const reference = Keypair.generate().publicKey;
+  await expectAnchorError(
+    payMerchant(customer, merchant, 250_000, reference, "x".repeat(101)),
+    "StringTooLong"
+  );
+  const paymentReceipt = await payMerchant(customer, merchant, 250_000, reference, "Latte #17");
+
+  // The point of sale polls the reference key for the transaction that paid its order
+  const signatures = await provider.connection.getSignaturesForAddress(reference, {}, "confirmed");
+  expect(signatures).to.have.lengthOf(1);
+  const receipt = await program.account.paymentReceipt.fetch(paymentReceipt);
+  expect(receipt.reference.toBase58()).to.equal(reference.toBase58());
+  expect(receipt.memo).to.equal("Latte #17");
+  expect(receipt.payee.toBase58()).to.equal(merchant.merchantProfile.toBase58());
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(250_000);
+});
+
+it("Pay several vaults in one batch payout", async () => {
+  const employer = await onboardUser("Payroll Employer");
+  const first = await onboardUser("Payroll First");
+  const second = await onboardUser("Payroll Second");
+  await fundVault(employer, 5_000_000);
+
+  const payoutGroup = (recipient: VaultUser) => [
+    { pubkey: recipient.vault, isWritable: false, isSigner: false },
+    { pubkey: recipient.vaultTokenAccount, isWritable: true, isSigner: false },
+    { pubkey: recipient.userBlacklist, isWritable: false, isSigner: false },
+  ];
+  const batchPayout = async (amounts: number[], groups: anchor.web3.AccountMeta[]) => {
+    const profile = await program.account.userProfile.fetch(employer.userProfile);
+    const payoutBatch = findPda(
+      Buffer.from("payout_batch"),
+      employer.keypair.publicKey.toBuffer(),
+      profile.payoutBatchCount.toArrayLike(Buffer, "le", 8)
+    );
+    await program.methods
+      .batchPayout(amounts.map((amount) => new anchor.BN(amount)), "March payroll")
+      .accounts({
+        user: employer.keypair.publicKey,
+        userBlacklist: employer.userBlacklist,
+        protocolConfig,
+        userProfile: employer.userProfile,
+        vault: employer.vault,
+        tokenWhitelist,
+        mint,
+        vaultTokenAccount: employer.vaultTokenAccount,
+        payoutBatch,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .remainingAccounts(groups)
+      .signers([employer.keypair])
+      .rpc();
+    return payoutBatch;
+  };
+
+  // Every amount needs its recipient's accounts, blacklist PDA included
+  await expectAnchorError(
+    batchPayout([1_200_000, 800_000], payoutGroup(first)),
+    "InvalidBatchAccounts"
+  );
+  const mismatched = payoutGroup(first);
+  mismatched[2] = payoutGroup(second)[2];
+  await expectAnchorError(batchPayout([1_200_000], mismatched), "InvalidBatchAccounts");
+  await expectAnchorError(
+    batchPayout([4_000_000, 2_000_000], [...payoutGroup(first), ...payoutGroup(second)]),
+    "InsufficientFunds"
+  );
+
+  const payoutBatch = await batchPayout(
+    [1_200_000, 800_000],
+    [...payoutGroup(first), ...payoutGroup(second)]
+  );
+  expect(await tokenBalance(employer.vaultTokenAccount)).to.equal(3_000_000);
+  expect(await tokenBalance(first.vaultTokenAccount)).to.equal(1_200_000);
+  expect(await tokenBalance(second.vaultTokenAccount)).to.equal(800_000);
+  const batch = await program.account.payoutBatch.fetch(payoutBatch);
+  expect(batch.recipientCount).to.equal(2);
+  expect(batch.totalAmount.toNumber()).to.equal(2_000_000);
+  expect(batch.memo).to.equal("March payroll");
+});
+
+it("Stream a payment per second and cancel it part way", async () => {
+  const sender = await onboardUser("Stream Sender");
+  const recipient = await onboardUser("Stream Recipient");
+  const outsider = Keypair.generate();
+  await fundVault(sender, 1_000_000);
+  const streamAuthority = findPda(Buffer.from("stream_authority"));
+  const streamTokenAccount = findPda(Buffer.from("stream_tokens"), mint.toBuffer());
+
+  const profile = await program.account.userProfile.fetch(sender.userProfile);
+  const paymentStream = findPda(
+    Buffer.from("payment_stream"),
+    sender.keypair.publicKey.toBuffer(),
+    profile.paymentStreamCount.toArrayLike(Buffer, "le", 8)
+  );
+  const createPaymentStream = (to: VaultUser, startTime: number, endTime: number) =>
+    program.methods
+      .createPaymentStream(
+        to.keypair.publicKey,
+        new anchor.BN(10_000),
+        new anchor.BN(startTime),
+        new anchor.BN(endTime)
+      )
+      .accounts({
+        user: sender.keypair.publicKey,
+        userBlacklist: sender.userBlacklist,
+        protocolConfig,
+        userProfile: sender.userProfile,
+        vault: sender.vault,
+        recipientBlacklist: to.userBlacklist,
+        recipientVault: to.vault,
+        allowlistEntry: null,
+        tokenWhitelist,
+        mint,
+        vaultTokenAccount: sender.vaultTokenAccount,
+        streamAuthority,
+        streamTokenAccount,
+        paymentStream,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([sender.keypair])
+      .rpc();
+
+  const startTime = (await chainTime()) + 2;
+  await expectAnchorError(
+    createPaymentStream(recipient, startTime - 10, startTime),
+    "InvalidStreamSchedule"
+  );
+  await expectAnchorError(
+    createPaymentStream(sender, startTime, startTime + 10),
+    "InvalidStreamRecipient"
+  );
+  // 10,000 a second for 60 seconds is escrowed up front
+  await createPaymentStream(recipient, startTime, startTime + 60);
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(400_000);
+
+  const withdrawFromStream = () =>
+    program.methods
+      .withdrawFromStream()
+      .accounts({
+        recipient: recipient.keypair.publicKey,
+        recipientBlacklist: recipient.userBlacklist,
+        paymentStream,
+        mint,
+        recipientVault: recipient.vault,
+        recipientVaultTokenAccount: recipient.vaultTokenAccount,
+        streamAuthority,
+        streamTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([recipient.keypair])
+      .rpc();
+  if ((await chainTime()) <= startTime) {
+    await expectAnchorError(withdrawFromStream(), "NothingToWithdraw");
+  }
+
+  await waitForChainTime(startTime + 3);
+  await withdrawFromStream();
+  const withdrawn = (await program.account.paymentStream.fetch(paymentStream)).withdrawnAmount.toNumber();
+  expect(withdrawn).to.be.greaterThan(0);
+  expect(withdrawn % 10_000).to.equal(0);
+  expect(await tokenBalance(recipient.vaultTokenAccount)).to.equal(withdrawn);
+
+  const cancelStream = (authority: Keypair) =>
+    program.methods
+      .cancelStream()
+      .accounts({
+        authority: authority.publicKey,
+        sender: sender.keypair.publicKey,
+        paymentStream,
+        mint,
+        senderVault: sender.vault,
+        senderVaultTokenAccount: sender.vaultTokenAccount,
+        recipientVault: recipient.vault,
+        recipientVaultTokenAccount: recipient.vaultTokenAccount,
+        streamAuthority,
+        streamTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([authority])
+      .rpc();
+  await expectAnchorError(cancelStream(outsider), "Unauthorized");
+  await cancelStream(sender.keypair);
+
+  // Accrued funds go to the recipient and the unaccrued rest back to the sender
+  const received = await tokenBalance(recipient.vaultTokenAccount);
+  expect(received).to.be.at.least(withdrawn);
+  expect(received).to.be.lessThan(600_000);
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(1_000_000 - received);
+  expect(await provider.connection.getAccountInfo(paymentStream)).to.be.null;
+});
+
+it("Refund a merchant payment up to what the merchant received", async () => {
+  const merchant = await registerMerchant(await onboardUser("Refunding Merchant"), "Shoe Store");
+  const otherMerchant = await registerMerchant(await onboardUser("Other Merchant"), "Hat Store");
+  await setMerchantTerms(merchant, 200, true);
+  const customer = await onboardUser("Refunded Customer");
+  await fundVault(customer, 1_000_000);
+  const paymentReceipt = await payMerchant(customer, merchant, 1_000_000);
+
+  const refundPayment = (by: Merchant, amount: number) =>
+    program.methods
+      .refundPayment(new anchor.BN(amount))
+      .accounts({
+        owner: by.owner.keypair.publicKey,
+        ownerBlacklist: by.owner.userBlacklist,
+        protocolConfig,
+        merchantProfile: by.merchantProfile,
+        paymentReceipt,
+        customerProfile: customer.userProfile,
+        customerVault: customer.vault,
+        tokenWhitelist,
+        mint,
+        merchantTokenAccount: by.merchantTokenAccount,
+        customerVaultTokenAccount: customer.vaultTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([by.owner.keypair])
+      .rpc();
+
+  // Only the merchant that was paid can refund the receipt
+  await expectAnchorError(refundPayment(otherMerchant, 100_000), "InvalidPaymentReceipt");
+
+  await refundPayment(merchant, 400_000);
+  expect(await tokenBalance(customer.vaultTokenAccount)).to.equal(400_000);
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(580_000);
+
+  // The 2% fee stays with the protocol, so at most 980,000 comes back in total
+  await expectAnchorError(refundPayment(merchant, 600_000), "RefundExceedsPayment");
+  await refundPayment(merchant, 580_000);
+  expect(await tokenBalance(customer.vaultTokenAccount)).to.equal(980_000);
+  expect(await tokenBalance(merchant.merchantTokenAccount)).to.equal(0);
+  const receipt = await program.account.paymentReceipt.fetch(paymentReceipt);
+  expect(receipt.refundedAmount.toNumber()).to.equal(980_000);
+  const profile = await program.account.merchantProfile.fetch(merchant.merchantProfile);
+  expect(profile.totalRefunded.toNumber()).to.equal(980_000);
+  expect(profile.refundCount.toNumber()).to.equal(2);
+});
+
+it("Keep emergency withdrawals closed until a pause has lasted 14 days", async () => {
+  const holder = await onboardUser("Emergency Holder");
+  const recipient = await onboardUser("Emergency Recipient");
+  await fundVault(holder, 1_000_000);
+  await claimHandle(recipient, "emergency_recipient");
+  const walletTokenAccount = await createAccount(provider.connection, admin, mint, holder.keypair.publicKey);
+
+  const emergencyWithdraw = () =>
+    program.methods
+      .emergencyWithdrawSpl()
+      .accounts({
+        user: holder.keypair.publicKey,
+        userBlacklist: holder.userBlacklist,
+        protocolConfig,
+        userProfile: holder.userProfile,
+        vault: holder.vault,
+        mint,
+        vaultTokenAccount: holder.vaultTokenAccount,
+        userTokenAccount: walletTokenAccount,
+        treasury,
+        protocolStats,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([holder.keypair])
+      .rpc();
+  await expectAnchorError(emergencyWithdraw(), "EmergencyWithdrawalUnavailable");
+
+  await program.methods
+    .pauseProtocol()
+    .accounts({
+      authority: admin.publicKey,
+      protocolConfig,
+      operatorRole: null,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([admin])
+    .rpc();
+  try {
+    const config = await program.account.protocolConfig.fetch(protocolConfig);
+    expect(config.pausedAt.toNumber()).to.be.greaterThan(0);
+    await expectAnchorError(
+      sendToUsername(holder, recipient, "emergency_recipient", 100_000),
+      "ProtocolPaused"
+    );
+    // The exit only opens 14 days into a pause, which a local validator cannot reach
+    await expectAnchorError(emergencyWithdraw(), "EmergencyWithdrawalUnavailable");
+  } finally {
+    await program.methods
+      .unpauseProtocol()
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([admin])
+      .rpc();
+  }
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+  expect(await tokenBalance(walletTokenAccount)).to.equal(0);
+});
+
+it("Guardians approve a recovery the owner can still cancel", async () => {
+  const owner = await onboardUser("Recovered Owner");
+  const [first, second, third] = [await fundedKeypair(), await fundedKeypair(), Keypair.generate()];
+  const outsider = await fundedKeypair();
+  const newOwner = await fundedKeypair();
+  const recoveryRequest = findPda(Buffer.from("recovery"), owner.keypair.publicKey.toBuffer());
+
+  const setGuardians = (guardians: PublicKey[], threshold: number) =>
+    program.methods
+      .setGuardians(guardians, threshold)
+      .accounts({
+        user: owner.keypair.publicKey,
+        userProfile: owner.userProfile,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([owner.keypair])
+      .rpc();
+  await expectAnchorError(
+    setGuardians([first.publicKey, first.publicKey], 1),
+    "InvalidGuardians"
+  );
+  await expectAnchorError(
+    setGuardians([first.publicKey, second.publicKey], 3),
+    "InvalidMultisigThreshold"
+  );
+  await setGuardians([first.publicKey, second.publicKey, third.publicKey], 2);
+
+  const proposeRecovery = (guardian: Keypair) =>
+    program.methods
+      .proposeRecovery(newOwner.publicKey)
+      .accounts({
+        guardian: guardian.publicKey,
+        userProfile: owner.userProfile,
+        recoveryRequest,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([guardian])
+      .rpc();
+  const approveRecovery = (guardian: Keypair) =>
+    program.methods
+      .approveRecovery()
+      .accounts({
+        guardian: guardian.publicKey,
+        userProfile: owner.userProfile,
+        recoveryRequest,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([guardian])
+      .rpc();
+  await expectAnchorError(proposeRecovery(outsider), "NotGuardian");
+  await proposeRecovery(first);
+  expect((await program.account.recoveryRequest.fetch(recoveryRequest)).approvedAt.toNumber()).to.equal(0);
+  await expectAnchorError(approveRecovery(first), "RecoveryAlreadyApproved");
+  await approveRecovery(second);
+  const approved = await program.account.recoveryRequest.fetch(recoveryRequest);
+  expect(approved.approvals).to.equal(0b011);
+  expect(approved.approvedAt.toNumber()).to.be.greaterThan(0);
+
+  // The owner has RECOVERY_DELAY (2 days) to notice and cancel before the new wallet takes over
+  const vaultAccount = await program.account.vault.fetch(owner.vault);
+  await expectAnchorError(
+    program.methods
+      .executeRecovery(vaultAccount.tokenAccountCount, vaultAccount.openPositions)
+      .accounts({
+        newOwner: newOwner.publicKey,
+        newOwnerBlacklist: findPda(Buffer.from("blacklist"), newOwner.publicKey.toBuffer()),
+        oldOwnerBlacklist: owner.userBlacklist,
+        proposer: first.publicKey,
+        protocolConfig,
+        recoveryRequest,
+        oldUserProfile: owner.userProfile,
+        oldVault: owner.vault,
+        newUserProfile: findPda(Buffer.from("user_profile"), newOwner.publicKey.toBuffer()),
+        newVault: findPda(Buffer.from("vault"), newOwner.publicKey.toBuffer()),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([newOwner])
+      .rpc(),
+    "RecoveryNotReady"
+  );
+
+  await program.methods
+    .cancelRecovery()
+    .accounts({
+      user: owner.keypair.publicKey,
+      proposer: first.publicKey,
+      recoveryRequest,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([owner.keypair])
+    .rpc();
+  expect(await provider.connection.getAccountInfo(recoveryRequest)).to.be.null;
+  const profile = await program.account.userProfile.fetch(owner.userProfile);
+  expect(profile.owner.toBase58()).to.equal(owner.keypair.publicKey.toBase58());
+});
+
+it("Block transfers to recipients not yet allowlisted", async () => {
+  const sender = await onboardUser("Allowlist Sender");
+  const recipient = await onboardUser("Allowlist Recipient");
+  await fundVault(sender, 2_000_000);
+  await claimHandle(recipient, "allowlist_recipient");
+
+  const setTransferAllowlist = (enabled: boolean) =>
+    program.methods
+      .setTransferAllowlist(enabled)
+      .accounts({
+        user: sender.keypair.publicKey,
+        userProfile: sender.userProfile,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([sender.keypair])
+      .rpc();
+  // Switching off is only allowed while the mode is on
+  await expectAnchorError(setTransferAllowlist(false), "AllowlistNotEnabled");
+  await setTransferAllowlist(true);
+  await expectAnchorError(
+    sendToUsername(sender, recipient, "allowlist_recipient", 100_000),
+    "RecipientNotAllowlisted"
+  );
+
+  const allowlistEntry = findPda(
+    Buffer.from("allowlist_entry"),
+    sender.keypair.publicKey.toBuffer(),
+    recipient.keypair.publicKey.toBuffer()
+  );
+  await program.methods
+    .addAllowlistEntry(recipient.keypair.publicKey)
+    .accounts({
+      user: sender.keypair.publicKey,
+      userProfile: sender.userProfile,
+      allowlistEntry,
+      systemProgram: SystemProgram.programId,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([sender.keypair])
+    .rpc();
+  const entry = await program.account.allowlistEntry.fetch(allowlistEntry);
+  expect(entry.activeAt.toNumber()).to.equal(entry.createdAt.toNumber() + 24 * 60 * 60);
+
+  // A new entry, like switching the mode off, only takes effect after a day
+  await expectAnchorError(
+    sendToUsername(sender, recipient, "allowlist_recipient", 100_000, sender.keypair, { allowlistEntry }),
+    "RecipientNotAllowlisted"
+  );
+  const invoice = await createPaymentRequest(recipient, recipient.keypair.publicKey, 100_000);
+  await expectAnchorError(payInvoice(sender, invoice, recipient, allowlistEntry), "RecipientNotAllowlisted");
+  await setTransferAllowlist(false);
+  await expectAnchorError(
+    sendToUsername(sender, recipient, "allowlist_recipient", 100_000),
+    "RecipientNotAllowlisted"
+  );
+  expect(await tokenBalance(sender.vaultTokenAccount)).to.equal(2_000_000);
+
+  await program.methods
+    .removeAllowlistEntry()
+    .accounts({
+      user: sender.keypair.publicKey,
+      allowlistEntry,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers([sender.keypair])
+    .rpc();
+  expect(await provider.connection.getAccountInfo(allowlistEntry)).to.be.null;
+});
+
+// Sets or clears `vaultUser`'s co-signer; replacing an existing one needs its signature as well
+const setCoSigner = (
+  vaultUser: VaultUser,
+  newCoSigner: PublicKey | null,
+  threshold: number,
+  currentCoSigner: Keypair | null = null
+) =>
+  program.methods
+    .setCoSigner(newCoSigner, new anchor.BN(threshold))
+    .accounts({
+      user: vaultUser.keypair.publicKey,
+      userProfile: vaultUser.userProfile,
+      currentCoSigner: currentCoSigner?.publicKey ?? null,
+      eventCounter,
+      eventAuthority,
+      program: program.programId,
+    })
+    .signers(currentCoSigner ? [vaultUser.keypair, currentCoSigner] : [vaultUser.keypair])
+    .rpc();
+
+it("Require the co-signer on withdrawals above the threshold", async () => {
+  const holder = await onboardUser("Co-signed Holder");
+  const coSigner = Keypair.generate();
+  const stranger = Keypair.generate();
+  await fundVault(holder, 2_000_000);
+
+  await expectAnchorError(setCoSigner(holder, holder.keypair.publicKey, 500_000), "InvalidCoSigner");
+  await setCoSigner(holder, coSigner.publicKey, 500_000);
+
+  await expectAnchorError(initiateFiatWithdrawal(holder, 600_000), "CoSignerRequired");
+  await expectAnchorError(
+    initiateFiatWithdrawal(holder, 600_000, { coSigner: stranger }),
+    "InvalidCoSigner"
+  );
+  // Below the threshold the owner alone is enough; above it the co-signer signs too
+  const small = await initiateFiatWithdrawal(holder, 400_000);
+  const large = await initiateFiatWithdrawal(holder, 600_000, { coSigner });
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(1_000_000);
+  await completeFiatWithdrawal(holder, small);
+  await completeFiatWithdrawal(holder, large);
+  expect(await withdrawalStatus(small)).to.equal("completed");
+  expect(await withdrawalStatus(large)).to.equal("completed");
+
+  // A stolen owner key alone cannot remove the co-signer
+  await expectAnchorError(setCoSigner(holder, null, 0), "CoSignerRequired");
+  await expectAnchorError(setCoSigner(holder, null, 0, stranger), "InvalidCoSigner");
+  await setCoSigner(holder, null, 0, coSigner);
+  const profile = await program.account.userProfile.fetch(holder.userProfile);
+  expect(profile.coSigner).to.be.null;
+  expect(profile.coSignerThreshold.toNumber()).to.equal(0);
+
+  await initiateFiatWithdrawal(holder, 600_000);
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(400_000);
+});
+
+it("Standing withdrawals need the co-signer whatever their amount", async () => {
+  const DAY = 24 * 60 * 60;
+  const holder = await onboardUser("Co-signed Salary Holder");
+  const coSigner = Keypair.generate();
+  await fundVault(holder, 1_000_000);
+
+  // An order created before the co-signer was set up stops running once it is
+  const earlyExecutionTs = (await chainTime()) + 3;
+  await createStandingWithdrawal(holder, 100_000, DAY, earlyExecutionTs);
+  await setCoSigner(holder, coSigner.publicKey, 500_000);
+  const caller = await fundedKeypair();
+  await waitForChainTime(earlyExecutionTs);
+  await expectAnchorError(executeStandingWithdrawal(holder, caller), "CoSignerRequired");
+  await cancelStandingWithdrawal(holder);
+
+  // Runs below the threshold still need the co-signer's approval of the order
+  const firstExecutionTs = (await chainTime()) + 3;
+  await expectAnchorError(
+    createStandingWithdrawal(holder, 100_000, DAY, firstExecutionTs),
+    "CoSignerRequired"
+  );
+  await createStandingWithdrawal(holder, 100_000, DAY, firstExecutionTs, 0, coSigner);
+  const order = await program.account.standingWithdrawal.fetch(standingWithdrawalAddress(holder));
+  expect(order.coSigner.toBase58()).to.equal(coSigner.publicKey.toBase58());
+
+  await waitForChainTime(firstExecutionTs);
+  const fiatWithdrawal = await executeStandingWithdrawal(holder, caller);
+  expect(await withdrawalStatus(fiatWithdrawal)).to.equal("pending");
+  expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(900_000);
+  await cancelStandingWithdrawal(holder);
+});
+
+it("Rate-limit deposit and withdrawal initiations per user per hour", async () => {
+  const setUserRateLimits = (maxDeposits: number, maxWithdrawals: number) =>
+    program.methods
+      .setUserRateLimits(maxDeposits, maxWithdrawals)
+      .accounts({
+        admin: admin.publicKey,
+        protocolConfig,
+      })
+      .signers([admin])
+      .rpc();
+  await expectAnchorError(setUserRateLimits(0, 5), "InvalidRateLimits");
+
+  const busy = await onboardUser("Busy Depositor");
+  const other = await onboardUser("Other Depositor");
+  await fundVault(busy, 1_000_000);
+  await setUserRateLimits(2, 1);
+  try {
+    await initiateVaultDeposit(busy, 100_000);
+    await initiateVaultDeposit(busy, 100_000);
+    await expectAnchorError(initiateVaultDeposit(busy, 100_000), "DepositRateLimitExceeded");
+    // The limit is counted per user
+    await initiateVaultDeposit(other, 100_000);
+
+    const limits = await program.account.userLimits.fetch(busy.userLimits);
+    expect(limits.hourlyDepositCount).to.equal(2);
+
+    const fiatWithdrawal = await initiateFiatWithdrawal(busy, 100_000);
+    await expectAnchorError(initiateFiatWithdrawal(busy, 100_000), "WithdrawalRateLimitExceeded");
+    expect((await program.account.userLimits.fetch(busy.userLimits)).hourlyWithdrawalCount).to.equal(1);
+    await completeFiatWithdrawal(busy, fiatWithdrawal);
+    expect(await tokenBalance(busy.vaultTokenAccount)).to.equal(900_000);
+  } finally {
+    await setUserRateLimits(4_294_967_295, 4_294_967_295);
+  }
+});
+
+it("Hold a disputed deposit and release it after a contest", async () => {
+  const complianceAuthority = await fundedKeypair();
+  await program.methods
+    .setComplianceAuthority(complianceAuthority.publicKey)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+    })
+    .signers([admin])
+    .rpc();
+
+  const depositor = await onboardUser("Disputed Depositor");
+  const fiatDeposit = await initiateVaultDeposit(depositor, 1_000_000);
+  await completeVaultDeposit(depositor, fiatDeposit);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(990_000);
+
+  const dispute = findPda(Buffer.from("dispute"), fiatDeposit.toBuffer());
+  const disputeAuthority = findPda(Buffer.from("dispute_authority"));
+  const disputeEscrow = findPda(Buffer.from("dispute_escrow"), mint.toBuffer());
+  const initiateClawback = (authority: Keypair, amount: number) =>
+    program.methods
+      .initiateClawback(new anchor.BN(amount), 1)
+      .accounts({
+        complianceAuthority: authority.publicKey,
+        protocolConfig,
+        fiatDeposit,
+        vault: depositor.vault,
+        mint,
+        vaultTokenAccount: depositor.vaultTokenAccount,
+        disputeAuthority,
+        disputeEscrow,
+        dispute,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([authority])
+      .rpc();
+  await expectAnchorError(initiateClawback(admin, 500_000), "Unauthorized");
+  await expectAnchorError(initiateClawback(complianceAuthority, 1_000_001), "InvalidClawbackAmount");
+  await initiateClawback(complianceAuthority, 500_000);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(490_000);
+
+  const contestClawback = () =>
+    program.methods
+      .contestClawback([...createHash("sha256").update("bank statement").digest()])
+      .accounts({
+        user: depositor.keypair.publicKey,
+        dispute,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([depositor.keypair])
+      .rpc();
+  await contestClawback();
+  expect((await program.account.dispute.fetch(dispute)).evidenceHash).to.not.be.null;
+
+  const resolveClawback = (reverse: boolean) =>
+    program.methods
+      .resolveClawback(reverse)
+      .accounts({
+        complianceAuthority: complianceAuthority.publicKey,
+        protocolConfig,
+        dispute,
+        fiatDeposit,
+        vault: depositor.vault,
+        mint,
+        tokenWhitelist,
+        vaultTokenAccount: depositor.vaultTokenAccount,
+        disputeAuthority,
+        disputeEscrow,
+        treasury,
+        treasuryTokenAccount,
+        protocolStats,
+        userTxIndex: depositor.userTxIndex,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([complianceAuthority])
+      .rpc();
+  // Reversal waits out the 7 day challenge window; releasing the hold does not
+  await expectAnchorError(resolveClawback(true), "ChallengeWindowOpen");
+  await resolveClawback(false);
+  expect(await tokenBalance(depositor.vaultTokenAccount)).to.equal(990_000);
+  const released = await program.account.dispute.fetch(dispute);
+  expect(Object.keys(released.status)[0]).to.equal("released");
+  expect(released.resolvedBy.toBase58()).to.equal(complianceAuthority.publicKey.toBase58());
+
+  await expectAnchorError(resolveClawback(false), "DisputeNotOpen");
+  await expectAnchorError(contestClawback(), "DisputeNotOpen");
+});
+
+it("Compliance vetoes a delayed large withdrawal within its veto window", async () => {
+  const setLargeWithdrawalDelay = (authority: Keypair, threshold: anchor.BN, delay: number) =>
+    program.methods
+      .setLargeWithdrawalDelay(threshold, new anchor.BN(delay))
+      .accounts({
+        admin: authority.publicKey,
+        protocolConfig,
+      })
+      .signers([authority])
+      .rpc();
+  const day = 24 * 60 * 60;
+
+  await expectAnchorError(setLargeWithdrawalDelay(user, new anchor.BN(1_000_000), day), "Unauthorized");
+  await expectAnchorError(setLargeWithdrawalDelay(admin, new anchor.BN(1_000_000), 0), "InvalidWithdrawalDelay");
+  await expectAnchorError(
+    setLargeWithdrawalDelay(admin, new anchor.BN(1_000_000), 7 * day + 1),
+    "InvalidWithdrawalDelay"
+  );
+
+  const complianceAuthority = await fundedKeypair();
+  await program.methods
+    .setComplianceAuthority(complianceAuthority.publicKey)
+    .accounts({
+      admin: admin.publicKey,
+      protocolConfig,
+    })
+    .signers([admin])
+    .rpc();
+  const holder = await onboardUser("Large Withdrawal Holder");
+  await fundVault(holder, 5_000_000);
+
+  const vetoWithdrawal = (authority: Keypair, fiatWithdrawal: PublicKey) =>
+    program.methods
+      .vetoWithdrawal(7)
+      .accounts({
+        complianceAuthority: authority.publicKey,
+        protocolConfig,
+        fiatWithdrawal,
+        vault: holder.vault,
+        vaultTokenAccount: holder.vaultTokenAccount,
+        mint,
+        escrowAuthority,
+        withdrawalEscrow: withdrawalEscrow(),
+        tokenProgram: TOKEN_PROGRAM_ID,
+        userTxIndex: holder.userTxIndex,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([authority])
+      .rpc();
+
+  // Withdrawals above $1 are held for 3 seconds
+  await setLargeWithdrawalDelay(admin, new anchor.BN(1_000_000), 3);
+  try {
+    const small = await initiateFiatWithdrawal(holder, 1_000_000);
+    expect(await withdrawalStatus(small)).to.equal("pending");
+    await expectAnchorError(vetoWithdrawal(complianceAuthority, small), "InvalidWithdrawalStatus");
+
+    const vetoed = await initiateFiatWithdrawal(holder, 1_500_000);
+    const held = await program.account.fiatWithdrawal.fetch(vetoed);
+    expect(Object.keys(held.status)[0]).to.equal("delayed");
+    expect(held.delayedUntil.toNumber()).to.equal(held.createdAt.toNumber() + 3);
+    await expectAnchorError(completeFiatWithdrawal(holder, vetoed), "WithdrawalDelayed");
+    await expectAnchorError(vetoWithdrawal(admin, vetoed), "Unauthorized");
+    await vetoWithdrawal(complianceAuthority, vetoed);
+    const refunded = await program.account.fiatWithdrawal.fetch(vetoed);
+    expect(Object.keys(refunded.status)[0]).to.equal("vetoed");
+    expect(refunded.rejectionReason).to.equal(7);
+    expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(4_000_000);
+
+    // Once the window passes the withdrawal settles as usual and can no longer be vetoed
+    const delayed = await initiateFiatWithdrawal(holder, 1_500_000);
+    const { delayedUntil } = await program.account.fiatWithdrawal.fetch(delayed);
+    await waitForChainTime(delayedUntil.toNumber());
+    await expectAnchorError(vetoWithdrawal(complianceAuthority, delayed), "InvalidWithdrawalStatus");
+    await completeFiatWithdrawal(holder, delayed);
+    await completeFiatWithdrawal(holder, small);
+    expect(await withdrawalStatus(delayed)).to.equal("completed");
+    expect(await tokenBalance(holder.vaultTokenAccount)).to.equal(2_500_000);
+  } finally {
+    await setLargeWithdrawalDelay(admin, new anchor.BN("18446744073709551615"), day);
+  }
+});
+
+it("Update a profile's name and email", async () => {
+  const member = await onboardUser("Original Name");
+  const updateUserProfile = (name: string, email: string) =>
+    program.methods
+      .updateUserProfile(name, email)
+      .accounts({
+        user: member.keypair.publicKey,
+        userProfile: member.userProfile,
+        systemProgram: SystemProgram.programId,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([member.keypair])
+      .rpc();
+
+  await expectAnchorError(updateUserProfile("x".repeat(51), "new@example.com"), "StringTooLong");
+  await expectAnchorError(updateUserProfile("Renamed", "x".repeat(101)), "StringTooLong");
+  // The longest allowed strings fit however short the profile's original ones were
+  await updateUserProfile("y".repeat(50), "z".repeat(100));
+  await updateUserProfile("Renamed Member", "renamed@example.com");
+  const profile = await program.account.userProfile.fetch(member.userProfile);
+  expect(profile.name).to.equal("Renamed Member");
+  expect(profile.email).to.equal("renamed@example.com");
+  expect(profile.owner.toBase58()).to.equal(member.keypair.publicKey.toBase58());
+});
+
+it("Close a profile once its vault is closed", async () => {
+  const leaver = await onboardUser("Closing Member");
+  await fundVault(leaver, 1_000_000);
+  // Withdraw everything first, which also creates the wallet's limits and stats
+  const fiatWithdrawal = await initiateFiatWithdrawal(leaver, 1_000_000);
+  await completeFiatWithdrawal(leaver, fiatWithdrawal);
+  const userStats = findPda(Buffer.from("user_stats"), leaver.keypair.publicKey.toBuffer());
+
+  const closeUserProfile = () =>
+    program.methods
+      .closeUserProfile()
+      .accounts({
+        user: leaver.keypair.publicKey,
+        userProfile: leaver.userProfile,
+        vault: leaver.vault,
+        userStats,
+        userTxIndex: leaver.userTxIndex,
+        protocolStats,
+        eventCounter,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([leaver.keypair])
+      .rpc();
+  await expectAnchorError(closeUserProfile(), "VaultNotClosed");
+  await closeVault(leaver, [leaver.vaultTokenAccount]);
+
+  // Handles would keep routing payments to the closed profile, so they are released first
+  const handleAccount = await claimHandle(leaver, "closing_member");
+  await expectAnchorError(closeUserProfile(), "HandlesNotReleased");
+  await program.methods
+    .releaseHandle()
+    .accounts({
+      user: leaver.keypair.publicKey,
+      userProfile: leaver.userProfile,
+      handleAccount,
+    })
+    .signers([leaver.keypair])
+    .rpc();
+
+  const statsBefore = await program.account.protocolStats.fetch(protocolStats);
+  await closeUserProfile();
+  expect(await provider.connection.getAccountInfo(leaver.userProfile)).to.be.null;
+  expect(await provider.connection.getAccountInfo(leaver.userTxIndex)).to.be.null;
+  const statsAfter = await program.account.protocolStats.fetch(protocolStats);
+  expect(statsAfter.userCount.toNumber()).to.equal(statsBefore.userCount.toNumber() - 1);
+  expect(await provider.connection.getAccountInfo(userStats)).to.be.null;
+  // Limits stay with the wallet, so closing and re-creating the profile cannot reset its windows
+  const limits = await program.account.userLimits.fetch(leaver.userLimits);
+  expect(limits.hourlyWithdrawalCount).to.equal(1);
+});
 });
-});
\ No newline at end of file